@@ -0,0 +1,97 @@
+use futures::stream::{self, StreamExt};
+use image::ImageFormat;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builder::{self, DEFAULT_TIMEOUT, DEFAULT_USER_AGENT},
+    error::Result,
+    ColorPaletteError, HandleImage, HandleImageBuilder,
+};
+
+fn palette_of(path: &Path, n: usize) -> Option<Vec<[u8; 3]>> {
+    match File::open(path)
+        .map_err(ColorPaletteError::from)
+        .and_then(HandleImage::set_from_reader)
+    {
+        Ok(mut image) => Some(
+            image
+                .get_top_colors(n)
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect(),
+        ),
+        Err(err) => {
+            log::warn!("skipping {}: {:#}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Extracts the `n` most frequent colors of every image in `dir`, sorted by path.
+/// Files that aren't images or fail to decode are skipped with a logged warning.
+/// With the `rayon` feature the files are processed in parallel.
+pub fn palette_from_dir(dir: &Path, n: usize) -> Result<Vec<(PathBuf, Vec<[u8; 3]>)>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if ImageFormat::from_path(&path).is_err() {
+            log::warn!("skipping {}: not a supported image file", path.display());
+            continue;
+        }
+        paths.push(path);
+    }
+    paths.sort();
+
+    #[cfg(feature = "rayon")]
+    let iter = paths.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = paths.into_iter();
+
+    Ok(iter
+        .filter_map(|path| palette_of(&path, n).map(|palette| (path, palette)))
+        .collect())
+}
+
+/// Downloads and extracts the `n` most frequent colors of every URL, with at most
+/// `concurrency` requests in flight over one shared client. Results are returned in
+/// completion order, each paired with its URL so failures can be told apart.
+pub async fn palettes_from_urls(
+    urls: &[String],
+    n: usize,
+    concurrency: usize,
+) -> Vec<(String, Result<Vec<[u8; 3]>>)> {
+    // `reqwest::Error` isn't `Clone`, so if the shared client can't be built every URL falls
+    // back to building its own and reports its own error.
+    let client = builder::web_client(DEFAULT_TIMEOUT, DEFAULT_USER_AGENT).ok();
+    let client = client.as_ref();
+    stream::iter(urls)
+        .map(|url| async move {
+            let image = match client {
+                Some(client) => {
+                    HandleImageBuilder::new()
+                        .from_url_with_client(client, url)
+                        .await
+                }
+                None => HandleImageBuilder::new().from_url(url).await,
+            };
+            let palette = image.map(|mut image| {
+                image
+                    .get_top_colors(n)
+                    .into_iter()
+                    .map(|(value, _)| value)
+                    .collect()
+            });
+            (url.clone(), palette)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+}
@@ -0,0 +1,348 @@
+use base64::Engine;
+use image::{
+    codecs::jpeg::JpegDecoder, imageops::FilterType, io::Reader as ImageReader, DynamicImage,
+    ImageFormat,
+};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Read, Seek},
+    path::Path,
+    time::Duration,
+};
+
+#[cfg(feature = "exif")]
+use crate::orientation;
+use crate::{cache, cmyk, error::Result, ico, ColorPaletteError, HandleImage};
+
+const DEFAULT_MAX_DIMENSION: u32 = 500;
+const DEFAULT_GRAYSCALE_THRESHOLD: u8 = 16;
+const JPEG_SCALE_FACTORS: [u32; 3] = [2, 4, 8];
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Configures how a [`HandleImage`] is loaded and analyzed.
+#[derive(Clone, Copy, Debug)]
+pub struct HandleImageBuilder {
+    pub(crate) max_dimension: u32,
+    pub(crate) filter: FilterType,
+    pub(crate) grayscale_threshold: u8,
+    pub(crate) exact_size: Option<(u32, u32)>,
+    pub(crate) preserve_colors: bool,
+}
+
+impl Default for HandleImageBuilder {
+    fn default() -> Self {
+        Self {
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            filter: FilterType::Triangle,
+            grayscale_threshold: DEFAULT_GRAYSCALE_THRESHOLD,
+            exact_size: None,
+            preserve_colors: false,
+        }
+    }
+}
+
+impl HandleImageBuilder {
+    pub fn new() -> HandleImageBuilder {
+        HandleImageBuilder::default()
+    }
+
+    /// Target length of the shorter side of the compressed analysis image.
+    /// A larger value gives more accurate palettes at the cost of speed.
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Filter used when compressing the image, `Triangle` by default.
+    /// `Nearest` preserves the true pixel colors, while `Triangle` and `Lanczos3` smooth them
+    /// and can introduce blended colors that were never in the original image.
+    pub fn filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Resizes the compressed analysis image to exactly `width` x `height`, ignoring the aspect
+    /// ratio and `max_dimension`. This distorts the image and is meant for fixed-size analysis
+    /// such as feature vectors, not for display.
+    pub fn resize_exact(mut self, width: u32, height: u32) -> Self {
+        self.exact_size = Some((width, height));
+        self
+    }
+
+    /// Downscales by keeping the most saturated (highest chroma) pixel of each block instead of
+    /// resampling with `filter`. Every palette color then occurs in the original image and small
+    /// vivid details survive, but the compressed image looks noisy and isn't meant for display.
+    pub fn preserve_colors(mut self, preserve_colors: bool) -> Self {
+        self.preserve_colors = preserve_colors;
+        self
+    }
+
+    /// Threshold used by [`HandleImage::is_grayscale`].
+    pub fn grayscale_threshold(mut self, grayscale_threshold: u8) -> Self {
+        self.grayscale_threshold = grayscale_threshold;
+        self
+    }
+
+    pub fn from_path<P: AsRef<Path>>(self, src: P) -> Result<HandleImage> {
+        Ok(HandleImage::from_decoded(
+            &decode_path(src.as_ref())?,
+            &self,
+        ))
+    }
+
+    /// Like [`from_path`](Self::from_path), but never decodes more than `max_pixels` pixels.
+    /// The dimensions are read from the header first; larger JPEGs are decoded at 1/2, 1/4 or
+    /// 1/8 scale, the largest that fits, and that reduced image becomes
+    /// [`HandleImage::image`]. Other formats can't be decoded at a reduced size, so an oversized
+    /// image of another format is an error.
+    pub fn from_path_with_memory_limit<P: AsRef<Path>>(
+        self,
+        src: P,
+        max_pixels: u64,
+    ) -> Result<HandleImage> {
+        let src = src.as_ref();
+        let reader = ImageReader::open(src)?.with_guessed_format()?;
+        let format = reader.format();
+        let (width, height) = reader.into_dimensions()?;
+        if width as u64 * height as u64 <= max_pixels {
+            return self.from_path(src);
+        }
+        if format != Some(ImageFormat::Jpeg) {
+            return Err(ColorPaletteError::UnsupportedFormat(format!(
+                "{}x{} image exceeds the limit of {} pixels and only JPEGs can be decoded at a \
+                 reduced size",
+                width, height, max_pixels
+            )));
+        }
+        let scaled = |factor: u32| (width.div_ceil(factor), height.div_ceil(factor));
+        let (scaled_width, scaled_height) = JPEG_SCALE_FACTORS
+            .iter()
+            .map(|factor| scaled(*factor))
+            .find(|(w, h)| *w as u64 * *h as u64 <= max_pixels)
+            .ok_or_else(|| {
+                ColorPaletteError::InvalidArgument(format!(
+                    "{}x{} JPEG exceeds the limit of {} pixels even at 1/8 scale",
+                    width, height, max_pixels
+                ))
+            })?;
+        let mut decoder = JpegDecoder::new(BufReader::new(File::open(src)?))?;
+        decoder.scale(scaled_width as u16, scaled_height as u16)?;
+        let image = DynamicImage::from_decoder(decoder)?;
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation::from_path(src));
+        Ok(HandleImage::from_decoded(&image, &self))
+    }
+
+    pub async fn from_url(self, src: &str) -> Result<HandleImage> {
+        let client = web_client(DEFAULT_TIMEOUT, DEFAULT_USER_AGENT)
+            .map_err(|err| ColorPaletteError::http(src, err))?;
+        self.from_url_with_client(&client, src).await
+    }
+
+    /// Downloads the image at `src` through an existing client, reusing its connection pool,
+    /// proxy and default headers.
+    pub async fn from_url_with_client(
+        self,
+        client: &reqwest::Client,
+        src: &str,
+    ) -> Result<HandleImage> {
+        let (result, content_type, cached) = match cache::load(src) {
+            Some(bytes) => (bytes, None, true),
+            None => {
+                let (bytes, content_type) = download(client, src).await?;
+                (bytes, content_type, false)
+            }
+        };
+        let image = decode_bytes_with_mime(&result, content_type.as_deref(), src)?;
+        // Only cache what decodes, or an error page served with a 200 would be replayed.
+        if !cached {
+            cache::store(src, &result);
+        }
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation::from_bytes(&result));
+        Ok(HandleImage::from_decoded(&image, &self))
+    }
+
+    /// Decodes a `data:image/png;base64,...` URI. Only base64 payloads are supported; the MIME
+    /// type is used as a fallback if the format can't be detected from the bytes.
+    pub fn from_data_uri(self, uri: &str) -> Result<HandleImage> {
+        let (header, payload) = uri
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(|| {
+                ColorPaletteError::InvalidArgument(
+                    "malformed data URI: expected `data:<mime type>;base64,<data>`".to_string(),
+                )
+            })?;
+        let (mime, encoding) = header.rsplit_once(';').unwrap_or((header, ""));
+        if !encoding.eq_ignore_ascii_case("base64") {
+            return Err(ColorPaletteError::InvalidArgument(format!(
+                "unsupported data URI encoding {:?}, only base64 is supported",
+                encoding
+            )));
+        }
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(payload.trim())
+            .map_err(|err| ColorPaletteError::Decode {
+                context: "malformed base64 payload in data URI".to_string(),
+                source: Some(Box::new(err)),
+            })?;
+        let mime = mime.split(';').next().unwrap_or(mime);
+        let image =
+            decode_bytes_with_mime(&bytes, Some(mime).filter(|m| !m.is_empty()), "data URI")?;
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation::from_bytes(&bytes));
+        Ok(HandleImage::from_decoded(&image, &self))
+    }
+
+    /// Analyzes an image that was already decoded elsewhere.
+    pub fn from_dynamic_image(self, image: DynamicImage) -> HandleImage {
+        HandleImage::from_decoded(&image, &self)
+    }
+
+    /// Decodes an image from a seekable stream, detecting its format from the contents.
+    pub fn from_reader<R: Read + Seek>(self, reader: R) -> Result<HandleImage> {
+        let reader = BufReader::new(reader);
+        #[cfg(feature = "exif")]
+        let (reader, orientation) = {
+            let mut reader = reader;
+            let start = reader.stream_position()?;
+            let orientation = orientation::from_reader(&mut reader);
+            reader.seek(std::io::SeekFrom::Start(start))?;
+            (reader, orientation)
+        };
+        let image = decode(ImageReader::new(reader).with_guessed_format()?)?;
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation);
+        Ok(HandleImage::from_decoded(&image, &self))
+    }
+
+    /// Decodes an image already held in memory, detecting its format from the bytes.
+    /// AVIF requires the `avif` feature; every other common format, WebP included,
+    /// is supported by default.
+    pub fn from_bytes(self, bytes: &[u8]) -> Result<HandleImage> {
+        let image = decode_bytes(bytes).map_err(|err| {
+            err.with_decode_context("failed to decode image from bytes: corrupt image data")
+        })?;
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation::from_bytes(bytes));
+        Ok(HandleImage::from_decoded(&image, &self))
+    }
+}
+
+/// Decodes through `image`, except for TIFFs which may hold CMYK data `image` cannot read
+/// and ICOs, where the largest image is picked instead of the one with the most bits per pixel.
+/// 16-bit and float images are scaled down to 8 bits with rounding when the RGB buffers are built.
+pub(crate) fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<DynamicImage> {
+    match reader.format() {
+        Some(ImageFormat::Tiff) => cmyk::decode_tiff(reader.into_inner()),
+        Some(ImageFormat::Ico) => {
+            let mut bytes = vec![];
+            reader.into_inner().read_to_end(&mut bytes)?;
+            decode_ico(&bytes, None)
+        }
+        _ => Ok(reader.decode()?),
+    }
+}
+
+/// Decodes the image at `index` of an ICO, or the largest one if `index` is `None`.
+/// Decodes the file at `src`, applying its EXIF orientation when the `exif` feature is enabled.
+pub(crate) fn decode_path(src: &Path) -> Result<DynamicImage> {
+    let image = decode(ImageReader::open(src)?)?;
+    #[cfg(feature = "exif")]
+    let image = orientation::apply(image, orientation::from_path(src));
+    Ok(image)
+}
+
+pub(crate) fn decode_ico(bytes: &[u8], index: Option<usize>) -> Result<DynamicImage> {
+    Ok(image::load_from_memory_with_format(
+        &ico::select(bytes, index)?,
+        ImageFormat::Ico,
+    )?)
+}
+
+fn decode_bytes(bytes: &[u8]) -> Result<DynamicImage> {
+    decode(ImageReader::new(Cursor::new(bytes)).with_guessed_format()?)
+}
+
+/// Detects the format from the bytes, falling back to the given MIME type if that fails.
+fn decode_bytes_with_mime(bytes: &[u8], mime: Option<&str>, src: &str) -> Result<DynamicImage> {
+    decode_bytes(bytes).or_else(|err| {
+        let mime = mime.unwrap_or("unknown");
+        match ImageFormat::from_mime_type(mime) {
+            Some(format) => decode_bytes_with_format(bytes, format),
+            None => Err(err),
+        }
+        .map_err(|err| {
+            err.with_decode_context(format!(
+                "failed to decode image from {} (content type: {})",
+                src, mime
+            ))
+        })
+    })
+}
+
+pub(crate) fn decode_bytes_with_format(bytes: &[u8], format: ImageFormat) -> Result<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(bytes));
+    reader.set_format(format);
+    decode(reader)
+}
+
+pub(crate) fn web_client(timeout: Duration, user_agent: &str) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .user_agent(user_agent)
+        .build()
+}
+
+async fn download(client: &reqwest::Client, src: &str) -> Result<(Vec<u8>, Option<String>)> {
+    let response = client
+        .get(src)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| ColorPaletteError::http(src, err))?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_string());
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|err| ColorPaletteError::http(src, err))?;
+    Ok((bytes.to_vec(), content_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+
+    // A 4x3 lossless WebP filled with [200, 100, 50].
+    const WEBP: &[u8] = include_bytes!("../testdata/solid-4x3.webp");
+
+    #[test]
+    fn from_bytes_decodes_webp() {
+        let mut image = HandleImageBuilder::new().from_bytes(WEBP).unwrap();
+        assert_eq!(image.get_dimensions(), [4, 3]);
+        assert_eq!(image.get_dominant_color(), [200, 100, 50]);
+        assert!(HandleImageBuilder::new().from_bytes(&WEBP[..20]).is_err());
+    }
+
+    #[test]
+    fn sixteen_bit_png_is_scaled_not_wrapped() {
+        // `DynamicImage::write_to` swaps the bytes of 16-bit PNGs in image 0.24.2, so the
+        // encoder is called directly with native-endian samples as it documents.
+        let samples: [u16; 6] = [65535, 0x1234, 0, 0xff00, 0x8000, 257];
+        let buf: Vec<u8> = samples.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let mut bytes = vec![];
+        PngEncoder::new(&mut bytes)
+            .write_image(&buf, 2, 1, ColorType::Rgb16)
+            .unwrap();
+        let mut image = HandleImageBuilder::new().from_bytes(&bytes).unwrap();
+        assert_eq!(image.get_colors_vec(), vec![[254, 128, 1], [255, 18, 0]]);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct CacheConfig {
+    dir: Option<PathBuf>,
+    max_age: Duration,
+}
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+static CONFIG: Mutex<CacheConfig> = Mutex::new(CacheConfig {
+    dir: None,
+    max_age: DEFAULT_MAX_AGE,
+});
+
+fn fnv1a(value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in value.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn entry(src: &str) -> Option<(PathBuf, Duration)> {
+    let config = CONFIG.lock().unwrap_or_else(|err| err.into_inner());
+    config
+        .dir
+        .as_ref()
+        .map(|dir| (dir.join(format!("{:016x}", fnv1a(src))), config.max_age))
+}
+
+pub(crate) fn set_dir(dir: Option<&Path>) {
+    let mut config = CONFIG.lock().unwrap_or_else(|err| err.into_inner());
+    config.dir = dir.map(Path::to_path_buf);
+}
+
+pub(crate) fn set_max_age(max_age: Duration) {
+    let mut config = CONFIG.lock().unwrap_or_else(|err| err.into_inner());
+    config.max_age = max_age;
+}
+
+/// Returns the cached bytes for `src` if caching is enabled and the entry isn't older than the max age.
+pub(crate) fn load(src: &str) -> Option<Vec<u8>> {
+    let (path, max_age) = entry(src)?;
+    let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age > max_age {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+/// Writes `bytes` to the cache. Failures are ignored since the cache is only an optimization.
+/// The bytes go to a temporary file that is renamed into place, so concurrent downloads of the
+/// same URL never read a half-written entry.
+pub(crate) fn store(src: &str, bytes: &[u8]) {
+    if let Some((path, _)) = entry(src) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let temp = path.with_extension(format!(
+            "tmp-{}-{}",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if fs::write(&temp, bytes).is_err() || fs::rename(&temp, &path).is_err() {
+            let _ = fs::remove_file(temp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_replaces_entry_without_leaving_temp_files() {
+        let dir = std::env::temp_dir().join(format!("image_colorpalette-cache-{}", process::id()));
+        set_dir(Some(&dir));
+        store("https://example.com/a.png", b"first");
+        store("https://example.com/a.png", b"second");
+        assert_eq!(load("https://example.com/a.png"), Some(b"second".to_vec()));
+        assert_eq!(load("https://example.com/b.png"), None);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        set_dir(None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use image::{codecs::tiff::TiffDecoder, DynamicImage, RgbImage};
+use std::io::{Read, Seek, SeekFrom};
+use tiff::decoder::{Decoder, DecodingResult};
+
+use crate::{error::Result, ColorPaletteError};
+
+/// Decodes a TIFF, converting CMYK data to RGB since `image` rejects it as unsupported.
+/// Every other color type is decoded by `image` as usual.
+pub(crate) fn decode_tiff<R: Read + Seek>(mut reader: R) -> Result<DynamicImage> {
+    let start = reader.stream_position()?;
+    let mut decoder = Decoder::new(&mut reader)?;
+    if let tiff::ColorType::CMYK(depth) = decoder.colortype()? {
+        let (width, height) = decoder.dimensions()?;
+        let rgb: Vec<u8> = match decoder.read_image()? {
+            DecodingResult::U8(data) => data
+                .chunks_exact(4)
+                .flat_map(|p| to_rgb([p[0], p[1], p[2], p[3]].map(|c| c as f32 / 255.0)))
+                .collect(),
+            DecodingResult::U16(data) => data
+                .chunks_exact(4)
+                .flat_map(|p| to_rgb([p[0], p[1], p[2], p[3]].map(|c| c as f32 / 65535.0)))
+                .collect(),
+            _ => {
+                return Err(ColorPaletteError::UnsupportedFormat(format!(
+                    "CMYK TIFF with bit depth {}",
+                    depth
+                )))
+            }
+        };
+        return RgbImage::from_raw(width, height, rgb)
+            .map(DynamicImage::ImageRgb8)
+            .ok_or_else(|| ColorPaletteError::decode("CMYK TIFF has truncated pixel data"));
+    }
+    drop(decoder);
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(DynamicImage::from_decoder(TiffDecoder::new(reader)?)?)
+}
+
+/// Converts ink coverages in `0.0..=1.0` with `R = 255 * (1 - C) * (1 - K)` and likewise
+/// for green and blue.
+fn to_rgb([c, m, y, k]: [f32; 4]) -> [u8; 3] {
+    let channel = |ink: f32| (255.0 * (1.0 - ink) * (1.0 - k)).round() as u8;
+    [channel(c), channel(m), channel(y)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn cmyk_tiff_is_converted_to_rgb() {
+        let pixels: [u8; 12] = [0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 255];
+        let mut bytes = Cursor::new(vec![]);
+        TiffEncoder::new(&mut bytes)
+            .unwrap()
+            .write_image::<colortype::CMYK8>(3, 1, &pixels)
+            .unwrap();
+        bytes.set_position(0);
+        let image = decode_tiff(bytes).unwrap().into_rgb8();
+        assert_eq!(image.dimensions(), (3, 1));
+        assert_eq!(
+            image.pixels().map(|pix| pix.0).collect::<Vec<_>>(),
+            vec![[255, 0, 0], [255, 255, 255], [0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn rgb_tiff_is_decoded_by_image() {
+        let pixels: [u8; 6] = [10, 20, 30, 40, 50, 60];
+        let mut bytes = Cursor::new(vec![]);
+        TiffEncoder::new(&mut bytes)
+            .unwrap()
+            .write_image::<colortype::RGB8>(2, 1, &pixels)
+            .unwrap();
+        bytes.set_position(0);
+        let image = decode_tiff(bytes).unwrap().into_rgb8();
+        assert_eq!(image.into_raw(), pixels);
+    }
+}
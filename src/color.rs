@@ -0,0 +1,390 @@
+use crate::{error::Result, ColorPaletteError};
+
+const D65: [f32; 3] = [0.95047, 1.0, 1.08883];
+const LAB_EPSILON: f32 = 216.0 / 24389.0;
+const LAB_KAPPA: f32 = 24389.0 / 27.0;
+
+pub(crate) type Lab = (f32, f32, f32);
+
+/// Converts an RGB color to HSL with hue in `0.0..360.0` and saturation and lightness in `0.0..=1.0`.
+pub fn rgb_to_hsl(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let r = rgb[0] as f32 / 255.0;
+    let g = rgb[1] as f32 / 255.0;
+    let b = rgb[2] as f32 / 255.0;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (h, s.min(1.0), l)
+}
+
+/// Converts an RGB color to HSV (also called HSB) with hue in `0.0..360.0` and saturation and
+/// value in `0.0..=1.0`. The hue matches [`rgb_to_hsl`], but saturation is relative to the
+/// brightest channel and value is that channel, so pure colors have `s == v == 1.0`.
+pub fn rgb_to_hsv(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let (h, _, _) = rgb_to_hsl(rgb);
+    let max = *rgb.iter().max().unwrap() as f32 / 255.0;
+    let min = *rgb.iter().min().unwrap() as f32 / 255.0;
+    if max == 0.0 {
+        return (h, 0.0, 0.0);
+    }
+    (h, (max - min) / max, max)
+}
+
+/// Converts HSV back to RGB. The hue wraps around and saturation and value are clamped to
+/// `0.0..=1.0`.
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+    let s = s.clamp(0.0, 1.0);
+    let v = v.clamp(0.0, 1.0);
+    let c = v * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
+/// Formats an RGB color as a lowercase `#rrggbb` string.
+pub fn to_hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Parses a `#rrggbb` or `rrggbb` string into an RGB color.
+pub fn from_hex(s: &str) -> Result<[u8; 3]> {
+    let digits = s.strip_prefix('#').unwrap_or(s);
+    if digits.len() != 6 {
+        return Err(ColorPaletteError::InvalidArgument(format!(
+            "invalid hex color {:?}: expected 6 hex digits",
+            s
+        )));
+    }
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ColorPaletteError::InvalidArgument(format!(
+            "invalid hex color {:?}: contains non-hex digits",
+            s
+        )));
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+pub(crate) fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    let c = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Converts an RGB color to CIELAB via XYZ using the D65 white point.
+pub fn rgb_to_lab(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let r = srgb_to_linear(rgb[0]);
+    let g = srgb_to_linear(rgb[1]);
+    let b = srgb_to_linear(rgb[2]);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / D65[0];
+    let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / D65[1];
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / D65[2];
+    let f = |t: f32| {
+        if t > LAB_EPSILON {
+            t.cbrt()
+        } else {
+            (LAB_KAPPA * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Converts a CIELAB color back to RGB via XYZ using the D65 white point, the inverse of
+/// [`rgb_to_lab`]. Colors outside the sRGB gamut are clamped per channel.
+pub fn lab_to_rgb(lab: (f32, f32, f32)) -> [u8; 3] {
+    let (l, a, b) = lab;
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f32| {
+        if t.powi(3) > LAB_EPSILON {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) / LAB_KAPPA
+        }
+    };
+    let x = f_inv(fx) * D65[0];
+    let y = f_inv(fy) * D65[1];
+    let z = f_inv(fz) * D65[2];
+    [
+        linear_to_srgb(3.2406 * x - 1.5372 * y - 0.4986 * z),
+        linear_to_srgb(-0.9689 * x + 1.8758 * y + 0.0415 * z),
+        linear_to_srgb(0.0557 * x - 0.2040 * y + 1.0570 * z),
+    ]
+}
+
+/// CIE76 color difference, the euclidean distance between two colors in CIELAB.
+pub fn delta_e(a: [u8; 3], b: [u8; 3]) -> f32 {
+    lab_distance(rgb_to_lab(a), rgb_to_lab(b))
+}
+
+/// Whether two colors are within `max_delta_e` of each other. Around 2.3 is the smallest
+/// difference most people notice.
+pub fn are_colors_similar(a: [u8; 3], b: [u8; 3], max_delta_e: f32) -> bool {
+    delta_e(a, b) <= max_delta_e
+}
+
+/// Whether any color of `palette` is within `max_delta_e` of `candidate`.
+pub fn palette_contains_similar(palette: &[[u8; 3]], candidate: [u8; 3], max_delta_e: f32) -> bool {
+    let candidate = rgb_to_lab(candidate);
+    palette
+        .iter()
+        .any(|value| lab_distance(rgb_to_lab(*value), candidate) <= max_delta_e)
+}
+
+pub(crate) fn lab_distance(a: Lab, b: Lab) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+/// Relative luminance as defined by WCAG, in `0.0..=1.0`.
+pub fn relative_luminance(rgb: [u8; 3]) -> f32 {
+    0.2126 * srgb_to_linear(rgb[0])
+        + 0.7152 * srgb_to_linear(rgb[1])
+        + 0.0722 * srgb_to_linear(rgb[2])
+}
+
+/// WCAG 2.1 contrast ratio between two colors, from `1.0` to `21.0`.
+pub fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let la = relative_luminance(a);
+    let lb = relative_luminance(b);
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// Returns black or white, whichever contrasts more with `bg`.
+pub fn best_text_color(bg: [u8; 3]) -> [u8; 3] {
+    let black = [0, 0, 0];
+    let white = [255, 255, 255];
+    if contrast_ratio(bg, black) >= contrast_ratio(bg, white) {
+        black
+    } else {
+        white
+    }
+}
+
+/// Converts HSL back to RGB. The hue wraps around, saturation and lightness are clamped to
+/// `0.0..=1.0`, and a saturation of zero gives a gray of the given lightness.
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [u8; 3] {
+    let s = s.clamp(0.0, 1.0);
+    let l = l.clamp(0.0, 1.0);
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
+/// Scales an RGB color to `0.0..=1.0` per channel, as graphics and ML libraries expect.
+pub fn normalize(rgb: [u8; 3]) -> [f32; 3] {
+    rgb.map(|channel| channel as f32 / 255.0)
+}
+
+/// Inverse of [`normalize`], rounding to the nearest value and clamping to `0.0..=1.0` first.
+pub fn denormalize(rgb: [f32; 3]) -> [u8; 3] {
+    rgb.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Packs an RGB color into a `0x00RRGGBB` integer.
+pub fn pack_rgb(rgb: [u8; 3]) -> u32 {
+    (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32
+}
+
+/// Unpacks a `0x00RRGGBB` integer into an RGB color, ignoring the top byte.
+pub fn unpack_rgb(value: u32) -> [u8; 3] {
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Interpolates from `a` at `t = 0.0` to `b` at `t = 1.0` in linear-light sRGB, so midpoints
+/// don't come out darker than they look. `t` is clamped to `0.0..=1.0`.
+pub fn mix(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |i: usize| {
+        let a = srgb_to_linear(a[i]);
+        linear_to_srgb(a + (srgb_to_linear(b[i]) - a) * t)
+    };
+    [channel(0), channel(1), channel(2)]
+}
+
+/// `steps` colors evenly spaced from `a` to `b` with [`mix`], both ends included.
+/// A single step returns `a`.
+pub fn gradient(a: [u8; 3], b: [u8; 3], steps: usize) -> Vec<[u8; 3]> {
+    if steps == 1 {
+        return vec![a];
+    }
+    (0..steps)
+        .map(|step| mix(a, b, step as f32 / (steps - 1) as f32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb_grid() -> impl Iterator<Item = [u8; 3]> {
+        (0..=255u8).step_by(15).flat_map(|r| {
+            (0..=255u8)
+                .step_by(15)
+                .flat_map(move |g| (0..=255u8).step_by(15).map(move |b| [r, g, b]))
+        })
+    }
+
+    fn assert_close(expected: [u8; 3], actual: [u8; 3], tolerance: u8) {
+        assert!(
+            expected
+                .iter()
+                .zip(actual)
+                .all(|(e, a)| e.abs_diff(a) <= tolerance),
+            "{:?} came back as {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for rgb in [[0, 0, 0], [255, 255, 255], [18, 52, 86], [171, 205, 239]] {
+            assert_eq!(from_hex(&to_hex(rgb)).unwrap(), rgb);
+        }
+        assert_eq!(to_hex([255, 0, 171]), "#ff00ab");
+        assert_eq!(from_hex("FF00AB").unwrap(), [255, 0, 171]);
+    }
+
+    #[test]
+    fn from_hex_rejects_malformed_input() {
+        for input in [
+            "", "#", "#fff", "#ff00ab0", "ff00a", "#gg0000", "#ff 0ab", "#ff00aé",
+        ] {
+            assert!(
+                matches!(from_hex(input), Err(ColorPaletteError::InvalidArgument(_))),
+                "{:?} was accepted",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn contrast_of_black_on_white_is_21() {
+        assert!((contrast_ratio([0, 0, 0], [255, 255, 255]) - 21.0).abs() < 1e-4);
+        assert_eq!(contrast_ratio([90, 30, 200], [90, 30, 200]), 1.0);
+        assert_eq!(
+            contrast_ratio([255, 255, 255], [12, 34, 56]),
+            contrast_ratio([12, 34, 56], [255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn best_text_color_picks_higher_contrast() {
+        assert_eq!(best_text_color([255, 255, 255]), [0, 0, 0]);
+        assert_eq!(best_text_color([255, 255, 0]), [0, 0, 0]);
+        assert_eq!(best_text_color([0, 0, 0]), [255, 255, 255]);
+        assert_eq!(best_text_color([0, 0, 139]), [255, 255, 255]);
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        for rgb in rgb_grid() {
+            let (h, s, l) = rgb_to_hsl(rgb);
+            assert_close(rgb, hsl_to_rgb(h, s, l), 1);
+        }
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        for rgb in rgb_grid() {
+            assert_eq!(unpack_rgb(pack_rgb(rgb)), rgb);
+        }
+        assert_eq!(pack_rgb([0x12, 0x34, 0x56]), 0x123456);
+        assert_eq!(unpack_rgb(0xff123456), [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn similar_colors_within_delta_e() {
+        assert!(are_colors_similar([120, 60, 200], [121, 60, 199], 2.3));
+        assert!(!are_colors_similar([120, 60, 200], [60, 200, 120], 2.3));
+        assert!(are_colors_similar([0, 0, 0], [255, 255, 255], 100.0));
+        let palette = [[255, 0, 0], [0, 128, 0]];
+        assert!(palette_contains_similar(&palette, [0, 129, 1], 2.3));
+        assert!(!palette_contains_similar(&palette, [0, 0, 255], 2.3));
+        assert!(!palette_contains_similar(&[], [0, 0, 255], 100.0));
+    }
+
+    #[test]
+    fn mix_blends_in_linear_light() {
+        assert_eq!(mix([0, 0, 0], [255, 255, 255], 0.5), [188, 188, 188]);
+        assert_eq!(mix([0, 0, 0], [255, 255, 255], 0.0), [0, 0, 0]);
+        assert_eq!(mix([0, 0, 0], [255, 255, 255], 2.0), [255, 255, 255]);
+        let steps = gradient([255, 0, 0], [0, 0, 255], 3);
+        assert_eq!(steps, vec![[255, 0, 0], [188, 0, 188], [0, 0, 255]]);
+    }
+
+    #[test]
+    fn normalize_round_trip() {
+        for rgb in rgb_grid() {
+            assert_eq!(denormalize(normalize(rgb)), rgb);
+        }
+        assert_eq!(normalize([255, 0, 51]), [1.0, 0.0, 0.2]);
+        assert_eq!(denormalize([1.5, -0.5, 0.5]), [255, 0, 128]);
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for rgb in rgb_grid() {
+            let (h, s, v) = rgb_to_hsv(rgb);
+            assert_close(rgb, hsv_to_rgb(h, s, v), 1);
+        }
+        assert_eq!(rgb_to_hsv([255, 0, 0]), (0.0, 1.0, 1.0));
+        assert_eq!(hsv_to_rgb(360.0, 1.0, 1.0), [255, 0, 0]);
+    }
+
+    #[test]
+    fn lab_round_trip() {
+        for rgb in rgb_grid() {
+            assert_close(rgb, lab_to_rgb(rgb_to_lab(rgb)), 2);
+        }
+        assert_eq!(lab_to_rgb((100.0, 0.0, 0.0)), [255, 255, 255]);
+        assert_eq!(lab_to_rgb((0.0, 0.0, 0.0)), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::color::{linear_to_srgb, srgb_to_linear};
+
+const RGB_TO_LMS: [[f32; 3]; 3] = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+const LMS_TO_RGB: [[f32; 3]; 3] = [
+    [0.080_944_45, -0.130_504_41, 0.116_721_07],
+    [-0.010_248_534, 0.054_019_33, -0.113_614_71],
+    [-0.000_365_296_94, -0.004_121_615, 0.693_511_4],
+];
+
+/// The dichromatic color vision deficiencies that can be simulated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorBlindness {
+    /// Missing long-wavelength (red) cones.
+    Protanopia,
+    /// Missing medium-wavelength (green) cones.
+    Deuteranopia,
+    /// Missing short-wavelength (blue) cones.
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    fn lms_projection(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => {
+                [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            }
+            ColorBlindness::Deuteranopia => {
+                [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]]
+            }
+            ColorBlindness::Tritanopia => {
+                [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-0.012245, 0.072035, 0.0]]
+            }
+        }
+    }
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (value, row) in result.iter_mut().zip(matrix) {
+        *value = row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+    }
+    result
+}
+
+/// Simulates how `rgb` appears with the given color vision deficiency, by projecting the
+/// color in LMS cone space onto the plane the dichromat can still distinguish (Viénot 1999).
+/// The projection planes keep white fixed, and also blue for protanopia and deuteranopia
+/// or red for tritanopia.
+pub fn simulate_color_blindness(rgb: [u8; 3], kind: ColorBlindness) -> [u8; 3] {
+    let linear = [
+        srgb_to_linear(rgb[0]),
+        srgb_to_linear(rgb[1]),
+        srgb_to_linear(rgb[2]),
+    ];
+    let lms = multiply(&RGB_TO_LMS, linear);
+    let simulated = multiply(&kind.lms_projection(), lms);
+    let result = multiply(&LMS_TO_RGB, simulated);
+    [
+        linear_to_srgb(result[0]),
+        linear_to_srgb(result[1]),
+        linear_to_srgb(result[2]),
+    ]
+}
@@ -0,0 +1,96 @@
+use std::{error::Error, io};
+use thiserror::Error;
+
+pub(crate) type Result<T> = std::result::Result<T, ColorPaletteError>;
+
+/// Everything that can go wrong while loading or analyzing an image.
+#[derive(Debug, Error)]
+pub enum ColorPaletteError {
+    /// Opening or reading a file or stream failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Building the client, sending the request, an error status or reading the body failed.
+    #[error("failed to fetch image from {url}")]
+    Http {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The data looks like a supported format but is corrupt or truncated.
+    #[error("{context}")]
+    Decode {
+        context: String,
+        #[source]
+        source: Option<Box<dyn Error + Send + Sync>>,
+    },
+    /// Writing an image such as a rendered palette failed.
+    #[error("failed to encode image")]
+    Encode(#[source] image::ImageError),
+    /// The format, or a feature of it such as a bit depth, can't be decoded.
+    #[error("unsupported image format: {0}")]
+    UnsupportedFormat(String),
+    /// An argument was out of range, such as a region outside the image bounds.
+    #[error("{0}")]
+    InvalidArgument(String),
+}
+
+impl ColorPaletteError {
+    pub(crate) fn decode(context: impl Into<String>) -> Self {
+        ColorPaletteError::Decode {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn http(url: &str, source: reqwest::Error) -> Self {
+        ColorPaletteError::Http {
+            url: url.to_string(),
+            source,
+        }
+    }
+
+    /// Replaces the message of a `Decode` error, keeping its source. Other variants are kept
+    /// as they are since they already say what failed.
+    pub(crate) fn with_decode_context(self, context: impl Into<String>) -> Self {
+        match self {
+            ColorPaletteError::Decode { source, .. } => ColorPaletteError::Decode {
+                context: context.into(),
+                source,
+            },
+            err => err,
+        }
+    }
+}
+
+impl From<image::ImageError> for ColorPaletteError {
+    fn from(err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(err) => ColorPaletteError::Io(err),
+            image::ImageError::Unsupported(err) => {
+                ColorPaletteError::UnsupportedFormat(err.to_string())
+            }
+            image::ImageError::Parameter(err) => {
+                ColorPaletteError::InvalidArgument(err.to_string())
+            }
+            err => ColorPaletteError::Decode {
+                context: "failed to decode image".to_string(),
+                source: Some(Box::new(err)),
+            },
+        }
+    }
+}
+
+impl From<tiff::TiffError> for ColorPaletteError {
+    fn from(err: tiff::TiffError) -> Self {
+        match err {
+            tiff::TiffError::IoError(err) => ColorPaletteError::Io(err),
+            tiff::TiffError::UnsupportedError(err) => {
+                ColorPaletteError::UnsupportedFormat(err.to_string())
+            }
+            err => ColorPaletteError::Decode {
+                context: "failed to decode TIFF".to_string(),
+                source: Some(Box::new(err)),
+            },
+        }
+    }
+}
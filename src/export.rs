@@ -0,0 +1,121 @@
+use crate::color;
+
+const ASE_SIGNATURE: &[u8; 4] = b"ASEF";
+const ASE_VERSION: (u16, u16) = (1, 0);
+const ASE_COLOR_ENTRY: u16 = 0x0001;
+const ASE_NORMAL_COLOR: u16 = 2;
+
+/// Formats `colors` as a GIMP palette, naming each swatch by its hex code.
+pub(crate) fn gpl(colors: &[[u8; 3]], name: &str) -> String {
+    let mut out = format!("GIMP Palette\nName: {}\nColumns: 0\n#\n", name);
+    for rgb in colors {
+        out.push_str(&format!(
+            "{:3} {:3} {:3}\t{}\n",
+            rgb[0],
+            rgb[1],
+            rgb[2],
+            color::to_hex(*rgb)
+        ));
+    }
+    out
+}
+
+/// Encodes `colors` as an Adobe Swatch Exchange file: big-endian, one RGB color entry
+/// per swatch with a UTF-16 hex name.
+pub(crate) fn ase(colors: &[[u8; 3]]) -> Vec<u8> {
+    let mut out = ASE_SIGNATURE.to_vec();
+    out.extend(ASE_VERSION.0.to_be_bytes());
+    out.extend(ASE_VERSION.1.to_be_bytes());
+    out.extend((colors.len() as u32).to_be_bytes());
+    for rgb in colors {
+        let mut block = vec![];
+        let name: Vec<u16> = color::to_hex(*rgb).encode_utf16().chain([0]).collect();
+        block.extend((name.len() as u16).to_be_bytes());
+        for unit in name {
+            block.extend(unit.to_be_bytes());
+        }
+        block.extend(b"RGB ");
+        for channel in rgb {
+            block.extend((*channel as f32 / 255.0).to_be_bytes());
+        }
+        block.extend(ASE_NORMAL_COLOR.to_be_bytes());
+        out.extend(ASE_COLOR_ENTRY.to_be_bytes());
+        out.extend((block.len() as u32).to_be_bytes());
+        out.extend(block);
+    }
+    out
+}
+
+/// Formats `colors` as CSS custom properties on `:root`, numbered from 1.
+pub(crate) fn css_variables(colors: &[[u8; 3]], prefix: &str) -> String {
+    let mut out = String::from(":root {\n");
+    for (i, rgb) in colors.iter().enumerate() {
+        out.push_str(&format!(
+            "  --{}-{}: {};\n",
+            prefix,
+            i + 1,
+            color::to_hex(*rgb)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Formats `colors` as a Tailwind `theme.extend.colors` entry named `palette`,
+/// using the numbered shades `palette-1`, `palette-2`, ...
+pub(crate) fn tailwind_config(colors: &[[u8; 3]]) -> String {
+    let mut out = String::from(
+        "module.exports = {\n  theme: {\n    extend: {\n      colors: {\n        palette: {\n",
+    );
+    for (i, rgb) in colors.iter().enumerate() {
+        out.push_str(&format!(
+            "          {}: '{}',\n",
+            i + 1,
+            color::to_hex(*rgb)
+        ));
+    }
+    out.push_str("        },\n      },\n    },\n  },\n};\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [[u8; 3]; 3] = [[255, 0, 0], [18, 52, 86], [0, 0, 0]];
+
+    #[test]
+    fn gpl_header_and_colors_parse_back() {
+        let out = gpl(&COLORS, "Sunset");
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("GIMP Palette"));
+        assert_eq!(lines.next(), Some("Name: Sunset"));
+        assert_eq!(lines.next(), Some("Columns: 0"));
+        assert_eq!(lines.next(), Some("#"));
+        let parsed: Vec<[u8; 3]> = lines
+            .map(|line| {
+                let (rgb, name) = line.split_once('\t').unwrap();
+                let rgb: Vec<u8> = rgb.split_whitespace().map(|v| v.parse().unwrap()).collect();
+                let rgb = [rgb[0], rgb[1], rgb[2]];
+                assert_eq!(name, color::to_hex(rgb));
+                rgb
+            })
+            .collect();
+        assert_eq!(parsed, COLORS);
+    }
+
+    #[test]
+    fn ase_header_counts_blocks() {
+        let out = ase(&COLORS);
+        assert_eq!(&out[0..4], ASE_SIGNATURE);
+        assert_eq!(out[4..8], [0, 1, 0, 0]);
+        assert_eq!(u32::from_be_bytes(out[8..12].try_into().unwrap()), 3);
+        let mut offset = 12;
+        for _ in 0..COLORS.len() {
+            assert_eq!(out[offset..offset + 2], ASE_COLOR_ENTRY.to_be_bytes());
+            let len = u32::from_be_bytes(out[offset + 2..offset + 6].try_into().unwrap());
+            offset += 6 + len as usize;
+        }
+        assert_eq!(offset, out.len());
+    }
+}
@@ -1,111 +1,1332 @@
-use anyhow::Result;
 use image::{
-    imageops::FilterType,
-    io::Reader as ImageReader,
-    ImageFormat, RgbImage, {self, DynamicImage},
+    codecs::gif::GifDecoder,
+    imageops::{self, FilterType},
+    AnimationDecoder, Frame, GenericImageView, ImageBuffer, ImageFormat, Pixel, Rgb, RgbImage,
+    Rgba, RgbaImage, {self, DynamicImage},
 };
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+    time::Duration,
+};
+
+use crate::builder::{self, DEFAULT_TIMEOUT, DEFAULT_USER_AGENT};
+#[cfg(feature = "exif")]
+use crate::orientation;
+use crate::{
+    cache, color, color_blindness, error::Result, export, named_colors, quantize, scheme,
+    ColorBlindness, ColorPaletteError, HandleImageBuilder, Palette,
+};
+
+const GRAYSCALE_SATURATION: f32 = 0.01;
+const ANALOGOUS_SPREAD: f32 = 30.0;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+const WARM_HUE: (f32, f32) = (330.0, 90.0);
+const COOL_HUE: (f32, f32) = (90.0, 270.0);
+const SEPIA_HUE: (f32, f32) = (20.0, 50.0);
+const SEPIA_MAX_CHROMA: f32 = 0.4;
+const SEPIA_NEUTRAL_CHROMA: f32 = 0.03;
+const SEPIA_MIN_RATIO: f32 = 0.9;
+const CHECKER_LIGHT: u8 = 255;
+const CHECKER_DARK: u8 = 204;
+const FOREGROUND_MIN_RATIO: f64 = 0.01;
+const SIMILARITY_MAX_DELTA_E: f32 = 50.0;
+const EXTREMES_MIN_RATIO: f64 = 0.001;
+const DIVERSE_CANDIDATES: usize = 256;
+const DIVERSE_BITS_PER_CHANNEL: u8 = 4;
+const PHASH_WIDTH: u32 = 8;
+const PHASH_HEIGHT: u32 = 8;
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK_PIXELS: usize = 16 * 1024;
 
+/// A loaded image with a downscaled copy for analysis and lazily computed color caches.
+///
+/// Cloning deep-copies the pixel buffers and caches, so a clone can be adjusted or
+/// re-analyzed independently without decoding the image again.
 #[allow(unused)]
+#[derive(Clone)]
 pub struct HandleImage {
     pub image: RgbImage,
     compressed_image: RgbImage,
+    compressed_rgba: RgbaImage,
+    compressed_adjusted: bool,
     colors: Option<HashSet<[u8; 3]>>,
+    color_counts: Option<HashMap<[u8; 3], u32>>,
+    grayscale_threshold: u8,
+    options: HandleImageBuilder,
 }
 
 impl HandleImage {
+    pub fn builder() -> HandleImageBuilder {
+        HandleImageBuilder::new()
+    }
+
     pub fn set(src: String) -> Result<HandleImage> {
-        let img = ImageReader::open(src)?.decode()?;
-        Ok(Self {
-            image: img.to_rgb8(),
-            compressed_image: HandleImage::compressing_image(&img),
-            colors: None,
-        })
+        HandleImage::set_from_path(src)
+    }
+
+    /// Opens the image at `path`, which unlike [`set`](Self::set) may be any `Path`,
+    /// including one that isn't valid UTF-8.
+    pub fn set_from_path<P: AsRef<Path>>(path: P) -> Result<HandleImage> {
+        HandleImageBuilder::new().from_path(path)
+    }
+
+    /// Opens the image at `src` and compresses it so its shorter side is at most `max_dimension`.
+    /// A larger value gives more accurate palettes at the cost of speed.
+    pub fn set_with_size(src: &str, max_dimension: u32) -> Result<HandleImage> {
+        HandleImageBuilder::new()
+            .max_dimension(max_dimension)
+            .from_path(src)
+    }
+
+    /// Opens the image at `src` without decoding more than `max_pixels` pixels, so untrusted
+    /// uploads can't exhaust memory. See [`HandleImageBuilder::from_path_with_memory_limit`].
+    pub fn set_with_memory_limit(src: &str, max_pixels: u64) -> Result<HandleImage> {
+        HandleImageBuilder::new().from_path_with_memory_limit(src, max_pixels)
+    }
+
+    /// Opens the image at `src` and compresses it with the given resize filter.
+    /// See [`HandleImageBuilder::filter`] for how the filters affect the palette.
+    pub fn set_with_filter(src: &str, filter: FilterType) -> Result<HandleImage> {
+        HandleImageBuilder::new().filter(filter).from_path(src)
+    }
+
+    /// Opens the image at `src` and downscales it for palette accuracy rather than display.
+    /// See [`HandleImageBuilder::preserve_colors`]; small vivid details such as a logo stay
+    /// in the palette instead of being averaged away.
+    pub fn set_preserve_colors(src: &str, max_dimension: u32) -> Result<HandleImage> {
+        HandleImageBuilder::new()
+            .max_dimension(max_dimension)
+            .preserve_colors(true)
+            .from_path(src)
+    }
+
+    /// Opens the image at `src` and resizes its compressed copy to exactly `w` x `h`.
+    /// See [`HandleImageBuilder::resize_exact`]; the image is distorted to fit.
+    pub fn set_resized_exact(src: &str, w: u32, h: u32, filter: FilterType) -> Result<HandleImage> {
+        HandleImageBuilder::new()
+            .resize_exact(w, h)
+            .filter(filter)
+            .from_path(src)
     }
 
     pub async fn set_from_web(src: String) -> Result<HandleImage> {
-        let result = reqwest::get(src).await?.bytes().await?;
-        let image = image::load_from_memory_with_format(&result, ImageFormat::Jpeg)?;
-        Ok(Self {
-            image: image.to_rgb8(),
-            compressed_image: HandleImage::compressing_image(&image),
-            colors: None,
-        })
+        HandleImage::set_from_web_with_options(&src, DEFAULT_TIMEOUT, DEFAULT_USER_AGENT).await
+    }
+
+    /// Downloads the image at `src` with a request `timeout` and a custom `user_agent`.
+    /// Network, timeout and HTTP status failures keep the underlying `reqwest::Error` as source.
+    pub async fn set_from_web_with_options(
+        src: &str,
+        timeout: Duration,
+        user_agent: &str,
+    ) -> Result<HandleImage> {
+        let client = builder::web_client(timeout, user_agent)
+            .map_err(|err| ColorPaletteError::http(src, err))?;
+        HandleImage::set_from_web_with_client(&client, src).await
+    }
+
+    /// Like `set_from_web`, but retries connection failures, timeouts and 5xx responses up to
+    /// `max_retries` times with exponential backoff (200ms, 400ms, 800ms, ...).
+    /// Other failures such as 4xx responses or undecodable images are returned immediately.
+    pub async fn set_from_web_with_retry(src: &str, max_retries: u32) -> Result<HandleImage> {
+        let client = builder::web_client(DEFAULT_TIMEOUT, DEFAULT_USER_AGENT)
+            .map_err(|err| ColorPaletteError::http(src, err))?;
+        let mut attempt = 0;
+        loop {
+            match HandleImage::set_from_web_with_client(&client, src).await {
+                Ok(image) => return Ok(image),
+                Err(err) if attempt < max_retries && HandleImage::is_transient(&err) => {
+                    tokio::time::sleep(RETRY_BASE_DELAY * 2u32.saturating_pow(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn is_transient(err: &ColorPaletteError) -> bool {
+        match err {
+            ColorPaletteError::Http { source, .. } => match source.status() {
+                Some(status) => status.is_server_error(),
+                None => {
+                    source.is_connect()
+                        || source.is_timeout()
+                        || source.is_request()
+                        || source.is_body()
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Downloads the image at `src` through an existing `reqwest::Client`.
+    pub async fn set_from_web_with_client(
+        client: &reqwest::Client,
+        src: &str,
+    ) -> Result<HandleImage> {
+        HandleImageBuilder::new()
+            .from_url_with_client(client, src)
+            .await
+    }
+
+    /// Downloads the image at `src` and compresses it so its shorter side is at most `max_dimension`.
+    /// A larger value gives more accurate palettes at the cost of speed.
+    pub async fn set_from_web_with_size(src: &str, max_dimension: u32) -> Result<HandleImage> {
+        HandleImageBuilder::new()
+            .max_dimension(max_dimension)
+            .from_url(src)
+            .await
+    }
+
+    /// Opens an animated GIF and analyzes frame number `frame`, clamped to the last frame.
+    pub fn set_from_gif_frame(src: &str, frame: usize) -> Result<HandleImage> {
+        let mut frames = HandleImage::gif_frames(src)?;
+        let frame = frames.swap_remove(frame.min(frames.len() - 1));
+        Ok(HandleImage::from_decoded(
+            &DynamicImage::ImageRgba8(frame.into_buffer()),
+            &HandleImageBuilder::new(),
+        ))
+    }
+
+    /// Builds an `n` color median-cut palette from the color counts of every frame of a GIF.
+    pub fn palette_across_frames(src: &str, n: usize) -> Result<Vec<[u8; 3]>> {
+        let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+        for frame in HandleImage::gif_frames(src)? {
+            let mut handle = HandleImage::from_decoded(
+                &DynamicImage::ImageRgba8(frame.into_buffer()),
+                &HandleImageBuilder::new(),
+            );
+            for (value, count) in handle.color_counts() {
+                *counts.entry(*value).or_insert(0) += count;
+            }
+        }
+        let mut colors: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+        colors.sort();
+        Ok(quantize::median_cut(&colors, n)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect())
+    }
+
+    fn gif_frames(src: &str) -> Result<Vec<Frame>> {
+        let decoder = GifDecoder::new(BufReader::new(File::open(src)?))?;
+        let frames = decoder.into_frames().collect_frames()?;
+        if frames.is_empty() {
+            return Err(ColorPaletteError::decode(format!(
+                "{} contains no frames",
+                src
+            )));
+        }
+        Ok(frames)
+    }
+
+    /// Caches images downloaded by the web loaders in `dir`, keyed by a hash of the URL.
+    /// Later downloads of the same URL are read from disk until the entry exceeds the max age.
+    pub fn set_cache_dir<P: AsRef<Path>>(dir: P) {
+        cache::set_dir(Some(dir.as_ref()));
+    }
+
+    /// Stops reading and writing the download cache.
+    pub fn disable_cache() {
+        cache::set_dir(None);
+    }
+
+    /// How long a cached download stays valid, one day by default.
+    pub fn set_cache_max_age(max_age: Duration) {
+        cache::set_max_age(max_age);
+    }
+
+    /// Builds a `HandleImage` from an already decoded image, skipping any re-encoding.
+    pub fn from_dynamic_image(img: DynamicImage) -> HandleImage {
+        HandleImageBuilder::new().from_dynamic_image(img)
+    }
+
+    /// Decodes an image from a seekable stream such as an upload or an archive entry.
+    pub fn set_from_reader<R: Read + Seek>(reader: R) -> Result<HandleImage> {
+        HandleImageBuilder::new().from_reader(reader)
     }
 
-    pub async fn set_from_bytes(
-        bytes: bytes::Bytes,
+    /// Decodes an image already held in memory, detecting its format from the bytes.
+    pub fn set_from_bytes(bytes: &[u8]) -> Result<HandleImage> {
+        HandleImageBuilder::new().from_bytes(bytes)
+    }
+
+    /// Decodes a base64 `data:` URI such as `data:image/png;base64,...`.
+    pub fn set_from_data_uri(uri: &str) -> Result<HandleImage> {
+        HandleImageBuilder::new().from_data_uri(uri)
+    }
+
+    /// Decodes an image already held in memory as `image_format`, without sniffing the bytes.
+    pub fn set_from_bytes_with_format(
+        bytes: &[u8],
         image_format: ImageFormat,
     ) -> Result<HandleImage> {
-        let image = image::load_from_memory_with_format(&bytes, image_format)?;
-        Ok(Self {
+        let image = builder::decode_bytes_with_format(bytes, image_format)?;
+        #[cfg(feature = "exif")]
+        let image = orientation::apply(image, orientation::from_bytes(bytes));
+        Ok(HandleImage::from_decoded(
+            &image,
+            &HandleImageBuilder::new(),
+        ))
+    }
+
+    /// Opens a favicon, analyzing the image at `index` of the ICO or, if `index` is `None`,
+    /// the largest one. [`set`](Self::set) also picks the largest image of an ICO.
+    pub fn set_ico(src: &str, index: Option<usize>) -> Result<HandleImage> {
+        let image = builder::decode_ico(&std::fs::read(src)?, index)?;
+        Ok(HandleImage::from_decoded(
+            &image,
+            &HandleImageBuilder::new(),
+        ))
+    }
+
+    pub(crate) fn from_decoded(image: &DynamicImage, options: &HandleImageBuilder) -> HandleImage {
+        let compressed = HandleImage::compress(image, options);
+        Self {
             image: image.to_rgb8(),
-            compressed_image: HandleImage::compressing_image(&image),
+            compressed_image: compressed.to_rgb8(),
+            compressed_rgba: compressed.to_rgba8(),
+            compressed_adjusted: false,
             colors: None,
-        })
+            color_counts: None,
+            grayscale_threshold: options.grayscale_threshold,
+            options: *options,
+        }
+    }
+
+    /// Builds the analysis image as configured by `options`.
+    fn compress(image: &DynamicImage, options: &HandleImageBuilder) -> DynamicImage {
+        match (options.exact_size, options.preserve_colors) {
+            (Some((width, height)), false) => image.resize_exact(width, height, options.filter),
+            (None, false) => {
+                HandleImage::compressing_image(image, options.max_dimension, options.filter)
+            }
+            (size, true) => {
+                let (width, height) = size
+                    .unwrap_or_else(|| HandleImage::compressed_size(image, options.max_dimension));
+                DynamicImage::ImageRgba8(HandleImage::vivid_downscale(
+                    &image.to_rgba8(),
+                    width,
+                    height,
+                ))
+            }
+        }
     }
 
-    fn compressing_image(image: &DynamicImage) -> RgbImage {
+    /// Overwrites `target` with `image` converted to 8 bits per channel, keeping the allocation
+    /// of `target`. `samples` are the raw samples of `image` if it already has `target`'s layout.
+    fn refill<P: Pixel<Subpixel = u8>>(
+        target: &mut ImageBuffer<P, Vec<u8>>,
+        image: &DynamicImage,
+        samples: Option<&[u8]>,
+    ) {
+        let mut raw = std::mem::take(target).into_raw();
+        raw.clear();
+        match samples {
+            Some(samples) => raw.extend_from_slice(samples),
+            None => {
+                let channels = P::CHANNEL_COUNT as usize;
+                raw.reserve(image.width() as usize * image.height() as usize * channels);
+                for (_, _, pix) in image.pixels() {
+                    raw.extend_from_slice(&pix.0[..channels]);
+                }
+            }
+        }
+        *target = ImageBuffer::from_raw(image.width(), image.height(), raw)
+            .expect("buffer holds every pixel");
+    }
+
+    fn compressing_image(
+        image: &DynamicImage,
+        max_dimension: u32,
+        filter: FilterType,
+    ) -> DynamicImage {
+        let (width, height) = HandleImage::compressed_size(image, max_dimension);
+        image.resize(width, height, filter)
+    }
+
+    fn compressed_size(image: &DynamicImage, max_dimension: u32) -> (u32, u32) {
         let width = image.width();
         let height = image.height();
-        let mut ratio = 500.0 / HandleImage::smaller(width, height) as f64;
+        let mut ratio = max_dimension as f64 / HandleImage::smaller(width, height) as f64;
         if ratio > 1.0 {
             ratio = 1.0;
         }
-        image
-            .resize(
-                HandleImage::calculate(width, ratio),
-                HandleImage::calculate(height, ratio),
-                FilterType::Triangle,
-            )
-            .to_rgb8()
+        (
+            HandleImage::calculate(width, ratio),
+            HandleImage::calculate(height, ratio),
+        )
+    }
+
+    /// Splits `image` into `width` x `height` blocks and keeps the pixel with the highest chroma
+    /// of each block, the first one on ties, so small vivid details survive the downscale.
+    fn vivid_downscale(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+        let (source_width, source_height) = image.dimensions();
+        if width == 0 || height == 0 || (width >= source_width && height >= source_height) {
+            return image.clone();
+        }
+        let span = |i: u32, size: u32, source: u32| {
+            let start = (i as u64 * source as u64 / size as u64) as u32;
+            let end = ((i as u64 + 1) * source as u64 / size as u64) as u32;
+            start..end.max(start + 1).min(source)
+        };
+        let chroma = |pix: &Rgba<u8>| {
+            let channels = &pix.0[..3];
+            channels.iter().max().unwrap() - channels.iter().min().unwrap()
+        };
+        RgbaImage::from_fn(width, height, |x, y| {
+            let mut best = *image.get_pixel(
+                span(x, width, source_width).start,
+                span(y, height, source_height).start,
+            );
+            for sy in span(y, height, source_height) {
+                for sx in span(x, width, source_width) {
+                    let pix = image.get_pixel(sx, sy);
+                    if chroma(pix) > chroma(&best) {
+                        best = *pix;
+                    }
+                }
+            }
+            best
+        })
+    }
+
+    /// Replaces this `HandleImage` with the image at `src`, compressed with the options it was
+    /// originally loaded with and keeping the grayscale threshold. All cached colors and counts
+    /// are invalidated. On error `self` is left unchanged.
+    ///
+    /// The pixel buffers of the old image are refilled in place and only grow when the new image
+    /// is larger, so reloading same-sized frames in a loop doesn't allocate them again. Decoding
+    /// and resizing still allocate temporary images.
+    pub fn reload<P: AsRef<Path>>(&mut self, src: P) -> Result<()> {
+        let image = builder::decode_path(src.as_ref())?;
+        let compressed = HandleImage::compress(&image, &self.options);
+        let samples = image.as_rgb8().map(|buffer| buffer.as_raw().as_slice());
+        HandleImage::refill(&mut self.image, &image, samples);
+        let samples = compressed
+            .as_rgb8()
+            .map(|buffer| buffer.as_raw().as_slice());
+        HandleImage::refill(&mut self.compressed_image, &compressed, samples);
+        let samples = compressed
+            .as_rgba8()
+            .map(|buffer| buffer.as_raw().as_slice());
+        HandleImage::refill(&mut self.compressed_rgba, &compressed, samples);
+        self.compressed_adjusted = false;
+        self.invalidate_cache();
+        Ok(())
+    }
+
+    /// Multiplies every channel of the compressed analysis image by `factor`, clamping to 0-255.
+    /// The original `image` is left untouched and cached colors are recomputed on next use.
+    pub fn adjust_brightness(&mut self, factor: f32) {
+        self.map_channels(|value| value as f32 * factor);
+    }
+
+    /// Applies a gamma curve to the compressed analysis image; values above 1.0 brighten and
+    /// values below 1.0 darken. The original `image` is left untouched and cached colors are
+    /// recomputed on next use.
+    pub fn adjust_gamma(&mut self, gamma: f32) {
+        self.map_channels(|value| 255.0 * (value as f32 / 255.0).powf(1.0 / gamma));
+    }
+
+    fn map_channels(&mut self, f: impl Fn(u8) -> f32) {
+        let mut table = [0u8; 256];
+        for (value, mapped) in table.iter_mut().enumerate() {
+            *mapped = f(value as u8).round().clamp(0.0, 255.0) as u8;
+        }
+        for pix in self.compressed_image.pixels_mut() {
+            for channel in pix.0.iter_mut() {
+                *channel = table[*channel as usize];
+            }
+        }
+        for pix in self.compressed_rgba.pixels_mut() {
+            for channel in pix.0[..3].iter_mut() {
+                *channel = table[*channel as usize];
+            }
+        }
+        self.compressed_adjusted = true;
+        self.invalidate_cache();
+    }
+
+    /// Drops the memoized colors and counts so they are recomputed on next use.
+    /// Call this after mutating the image buffers directly, or stale palettes are returned.
+    pub fn invalidate_cache(&mut self) {
+        self.colors = None;
+        self.color_counts = None;
+    }
+
+    /// Computes and caches the unique colors and their counts, then returns `self`, so loading
+    /// and analysis chain as in `HandleImage::set(src)?.compute_colors()`.
+    pub fn compute_colors(mut self) -> Self {
+        self.get_colors();
+        self
+    }
+
+    /// Chainable [`adjust_brightness`](Self::adjust_brightness).
+    pub fn with_brightness(mut self, factor: f32) -> Self {
+        self.adjust_brightness(factor);
+        self
+    }
+
+    /// Chainable [`adjust_gamma`](Self::adjust_gamma).
+    pub fn with_gamma(mut self, gamma: f32) -> Self {
+        self.adjust_gamma(gamma);
+        self
+    }
+
+    /// Chainable [`set_grayscale_threshold`](Self::set_grayscale_threshold).
+    pub fn with_grayscale_threshold(mut self, threshold: u8) -> Self {
+        self.set_grayscale_threshold(threshold);
+        self
+    }
+
+    /// Returns a luminance-only copy of this image, converted with the Rec. 709 luma weights
+    /// and with fresh caches. Alpha is preserved and `self` is left untouched.
+    pub fn to_grayscale(&self) -> HandleImage {
+        let luma = |image: &RgbImage| DynamicImage::ImageRgb8(image.clone()).grayscale();
+        HandleImage {
+            image: luma(&self.image).to_rgb8(),
+            compressed_image: luma(&self.compressed_image).to_rgb8(),
+            compressed_rgba: DynamicImage::ImageRgba8(self.compressed_rgba.clone())
+                .grayscale()
+                .to_rgba8(),
+            compressed_adjusted: true,
+            colors: None,
+            color_counts: None,
+            grayscale_threshold: self.grayscale_threshold,
+            options: self.options,
+        }
     }
 
     pub fn get_colors(&mut self) -> HashSet<[u8; 3]> {
-        return match &self.colors {
+        match &self.colors {
             Some(value) => value.clone(),
             None => {
-                let mut seen = HashSet::new();
-                for pix in self.compressed_image.pixels() {
-                    seen.insert([pix[0], pix[1], pix[2]]);
-                }
+                let seen: HashSet<[u8; 3]> = self.color_counts().keys().copied().collect();
                 self.colors = Some(seen.clone());
                 seen
             }
+        }
+    }
+
+    /// Returns the unique colors within the `w` x `h` rectangle at `x`, `y` of the full-resolution
+    /// image. The cached colors of the whole image are left untouched.
+    pub fn get_colors_in_region(
+        &mut self,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+    ) -> Result<HashSet<[u8; 3]>> {
+        let [width, height] = self.get_dimensions();
+        match (x.checked_add(w), y.checked_add(h)) {
+            (Some(right), Some(bottom)) if right <= width && bottom <= height => {}
+            _ => {
+                return Err(ColorPaletteError::InvalidArgument(format!(
+                    "region {}x{} at ({}, {}) exceeds the image bounds of {}x{}",
+                    w, h, x, y, width, height
+                )))
+            }
+        }
+        Ok(self
+            .image
+            .view(x, y, w, h)
+            .pixels()
+            .map(|(_, _, pix)| [pix[0], pix[1], pix[2]])
+            .collect())
+    }
+
+    /// Bounding box `[min_x, min_y, max_x, max_y]` (inclusive) of the full-resolution pixels
+    /// whose every channel is within `tolerance` of `rgb`, or `None` if there are none.
+    pub fn color_bounds(&self, rgb: [u8; 3], tolerance: u8) -> Option<[u32; 4]> {
+        let mut bounds: Option<[u32; 4]> = None;
+        for (x, y, pix) in self.image.enumerate_pixels() {
+            if !HandleImage::within_tolerance(pix.0, rgb, tolerance) {
+                continue;
+            }
+            bounds = Some(match bounds {
+                Some([min_x, min_y, max_x, max_y]) => {
+                    [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+                }
+                None => [x, y, x, y],
+            });
+        }
+        bounds
+    }
+
+    /// Guesses the background color from the border rows and columns of the full-resolution
+    /// image: the border color with the most border pixels within `tolerance` per channel of it.
+    /// Unlike [`get_dominant_color`](Self::get_dominant_color) a large centered subject can't win.
+    pub fn get_background_color(&self, tolerance: u8) -> [u8; 3] {
+        let [width, height] = self.get_dimensions();
+        let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+        for (x, y, pix) in self.image.enumerate_pixels() {
+            if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                *counts.entry(pix.0).or_insert(0) += 1;
+            }
+        }
+        let mut clustered: HashMap<[u8; 3], u32> = HashMap::new();
+        for candidate in counts.keys() {
+            let total = counts
+                .iter()
+                .filter(|(value, _)| HandleImage::within_tolerance(*candidate, **value, tolerance))
+                .map(|(_, count)| count)
+                .sum();
+            clustered.insert(*candidate, total);
+        }
+        HandleImage::most_frequent(&clustered)
+    }
+
+    /// Returns the unique colors of the pixels whose alpha is at least `alpha_threshold`,
+    /// so transparent regions don't contribute to the palette.
+    pub fn get_colors_ignoring_transparent(&mut self, alpha_threshold: u8) -> HashSet<[u8; 3]> {
+        self.compressed_rgba
+            .pixels()
+            .filter(|pix| pix[3] >= alpha_threshold)
+            .map(|pix| [pix[0], pix[1], pix[2]])
+            .collect()
+    }
+
+    /// Number of unique colors, computed lazily without cloning the cached colors.
+    pub fn count_unique_colors(&mut self) -> usize {
+        match &self.colors {
+            Some(colors) => colors.len(),
+            None => self.color_counts().len(),
+        }
+    }
+
+    /// Returns the unique colors in ascending `0xRRGGBB` order, for reproducible output.
+    pub fn get_colors_vec(&mut self) -> Vec<[u8; 3]> {
+        let mut colors: Vec<[u8; 3]> = self.color_counts().keys().copied().collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    /// Returns the unique colors scaled to `0.0..=1.0` with [`normalize`](crate::normalize),
+    /// in the same order as [`get_colors_vec`](Self::get_colors_vec).
+    pub fn get_colors_normalized(&mut self) -> Vec<[f32; 3]> {
+        self.get_colors_vec()
+            .into_iter()
+            .map(color::normalize)
+            .collect()
+    }
+
+    /// Returns the unique colors packed as `0x00RRGGBB`, in ascending order.
+    pub fn get_colors_packed(&mut self) -> Vec<u32> {
+        let mut colors: Vec<u32> = self
+            .color_counts()
+            .keys()
+            .map(|v| color::pack_rgb(*v))
+            .collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    /// Returns how many pixels of the compressed image have each color.
+    pub fn get_color_counts(&mut self) -> HashMap<[u8; 3], u32> {
+        self.color_counts().clone()
+    }
+
+    /// Like [`get_color_counts`](Self::get_color_counts), but calls `progress` with the fraction
+    /// of the compressed image scanned so far, once per pixel row, ending with `1.0`.
+    /// Cached counts are returned right away after a single call with `1.0`.
+    pub fn get_color_counts_with_progress(
+        &mut self,
+        mut progress: impl FnMut(f32),
+    ) -> HashMap<[u8; 3], u32> {
+        let height = self.compressed_image.height();
+        if self.color_counts.is_some() || height == 0 {
+            progress(1.0);
+            return self.color_counts().clone();
+        }
+        let mut counts = HashMap::new();
+        for (y, row) in self.compressed_image.rows().enumerate() {
+            for pix in row {
+                *counts.entry([pix[0], pix[1], pix[2]]).or_insert(0) += 1;
+            }
+            progress((y + 1) as f32 / height as f32);
+        }
+        self.color_counts = Some(counts.clone());
+        counts
+    }
+
+    /// Counts only every `stride`-th pixel of the compressed image in row-major order, for a
+    /// rough palette of many images. Counts shrink by about `stride`, colors covering few pixels
+    /// may be missed and regular patterns can alias with the stride. A `stride` of 1, or 0, is
+    /// a full scan. The cached counts are neither used nor updated.
+    pub fn get_color_counts_sampled(&self, stride: usize) -> HashMap<[u8; 3], u32> {
+        let mut counts = HashMap::new();
+        for pix in self.compressed_image.pixels().step_by(stride.max(1)) {
+            *counts.entry([pix[0], pix[1], pix[2]]).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Iterates over the cached color counts without cloning them, computing them first if needed.
+    pub fn color_count_iter(&mut self) -> impl Iterator<Item = (&[u8; 3], &u32)> + '_ {
+        self.color_counts().iter()
+    }
+
+    /// Reduces each channel to its top `bits_per_channel` bits and counts the pixels per bucket.
+    /// Every bucket is keyed by its center color, e.g. 4 bits give at most 4096 buckets.
+    pub fn get_colors_quantized(&mut self, bits_per_channel: u8) -> Result<HashMap<[u8; 3], u32>> {
+        if !(1..=8).contains(&bits_per_channel) {
+            return Err(ColorPaletteError::InvalidArgument(format!(
+                "bits_per_channel must be between 1 and 8, got {}",
+                bits_per_channel
+            )));
+        }
+        let shift = 8 - bits_per_channel;
+        let center = |channel: u8| match shift {
+            0 => channel,
+            _ => (channel >> shift << shift) | (1 << (shift - 1)),
         };
+        let mut quantized = HashMap::new();
+        for (value, count) in self.color_counts() {
+            *quantized
+                .entry([center(value[0]), center(value[1]), center(value[2])])
+                .or_insert(0) += count;
+        }
+        Ok(quantized)
+    }
+
+    /// Returns the unique colors after rounding every channel to the nearest multiple of
+    /// `bucket_size`, capped at 255, collapsing JPEG noise into representative colors. Larger
+    /// buckets give a cleaner but coarser set. `bucket_size` must be between 1 and 128.
+    pub fn get_colors_bucketed(&mut self, bucket_size: u8) -> Result<HashSet<[u8; 3]>> {
+        if !(1..=128).contains(&bucket_size) {
+            return Err(ColorPaletteError::InvalidArgument(format!(
+                "bucket_size must be between 1 and 128, got {}",
+                bucket_size
+            )));
+        }
+        let size = bucket_size as u32;
+        let round = |channel: u8| ((channel as u32 + size / 2) / size * size).min(255) as u8;
+        Ok(self
+            .color_counts()
+            .keys()
+            .map(|value| value.map(round))
+            .collect())
+    }
+
+    /// Returns the `n` most frequent colors with their pixel counts, most frequent first.
+    pub fn get_top_colors(&mut self, n: usize) -> Vec<([u8; 3], u32)> {
+        self.top_n_where(|_| true, n)
+    }
+
+    /// Returns the `n` most frequent colors of the subject: colors within `bg_tolerance` per
+    /// channel of [`get_background_color`](Self::get_background_color) are left out.
+    /// Falls back to the unfiltered palette if less than 1% of the pixels aren't background.
+    pub fn get_foreground_palette(&mut self, n: usize, bg_tolerance: u8) -> Vec<[u8; 3]> {
+        let background = self.get_background_color(bg_tolerance);
+        let is_foreground =
+            |value: &[u8; 3]| !HandleImage::within_tolerance(*value, background, bg_tolerance);
+        let mut total = 0u64;
+        let mut foreground = 0u64;
+        for (value, count) in self.color_counts() {
+            total += *count as u64;
+            if is_foreground(value) {
+                foreground += *count as u64;
+            }
+        }
+        if (foreground as f64) < total as f64 * FOREGROUND_MIN_RATIO {
+            return self.top_color_values(n);
+        }
+        self.top_n_where(is_foreground, n)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    /// Returns the `n` most frequent colors after dropping those within `tolerance` per channel
+    /// of any `excluded` color, such as a green screen or the toolbar of a screenshot.
+    pub fn get_palette_excluding(
+        &mut self,
+        excluded: &[[u8; 3]],
+        tolerance: u8,
+        n: usize,
+    ) -> Vec<[u8; 3]> {
+        self.top_n_where(
+            |value| {
+                !excluded
+                    .iter()
+                    .any(|key| HandleImage::within_tolerance(*value, *key, tolerance))
+            },
+            n,
+        )
+        .into_iter()
+        .map(|(value, _)| value)
+        .collect()
     }
 
+    /// Returns the `n` most frequent colors whose HSL lightness lies within
+    /// `min_lightness..=max_lightness`, dropping dark shadows and bright highlights.
+    /// Falls back to the unfiltered palette if no color is within range.
+    pub fn get_palette_filtered(
+        &mut self,
+        n: usize,
+        min_lightness: f32,
+        max_lightness: f32,
+    ) -> Vec<[u8; 3]> {
+        let palette = self.top_n_where(
+            |value| {
+                let (_, _, l) = color::rgb_to_hsl(*value);
+                l >= min_lightness && l <= max_lightness
+            },
+            n,
+        );
+        if palette.is_empty() {
+            return self.top_color_values(n);
+        }
+        palette.into_iter().map(|(value, _)| value).collect()
+    }
+
+    /// Returns the `n` darkest colors, darkest first, and the `n` lightest colors, lightest first,
+    /// by relative luminance. Colors covering less than 0.1% of the pixels are skipped as noise,
+    /// unless no color is that frequent, as in a smooth gradient.
+    pub fn get_extremes(&mut self, n: usize) -> (Vec<[u8; 3]>, Vec<[u8; 3]>) {
+        let counts = self.color_counts();
+        let total: u64 = counts.values().map(|count| *count as u64).sum();
+        let min_count = (total as f64 * EXTREMES_MIN_RATIO).ceil() as u32;
+        let mut colors: Vec<(f32, [u8; 3])> = counts
+            .iter()
+            .filter(|(_, count)| **count >= min_count)
+            .map(|(value, _)| (color::relative_luminance(*value), *value))
+            .collect();
+        if colors.is_empty() {
+            colors = counts
+                .keys()
+                .map(|value| (color::relative_luminance(*value), *value))
+                .collect();
+        }
+        colors.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let darkest = colors.iter().take(n).map(|(_, value)| *value).collect();
+        let lightest = colors
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(_, value)| *value)
+            .collect();
+        (darkest, lightest)
+    }
+
+    /// Returns the `n` least frequent colors that appear at least `min_count` times,
+    /// rarest first. `min_count` filters out single-pixel noise.
+    pub fn get_least_frequent_colors(&mut self, n: usize, min_count: u32) -> Vec<([u8; 3], u32)> {
+        let mut counts: Vec<([u8; 3], u32)> = self
+            .color_counts()
+            .iter()
+            .filter(|(_, count)| **count >= min_count)
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        counts.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Returns the unique colors as lowercase `#rrggbb` strings.
+    pub fn get_colors_hex(&mut self) -> Vec<String> {
+        self.get_colors().into_iter().map(color::to_hex).collect()
+    }
+
+    /// Returns the unique colors ordered by HSL hue, then saturation, then lightness.
+    /// Grayscale colors are grouped at the end, ordered by lightness.
+    pub fn get_colors_sorted_by_hue(&mut self) -> Vec<[u8; 3]> {
+        let mut colors: Vec<([u8; 3], (f32, f32, f32))> = self
+            .get_colors()
+            .into_iter()
+            .map(|value| (value, color::rgb_to_hsl(value)))
+            .collect();
+        colors.sort_by(|(_, a), (_, b)| {
+            let a_gray = a.1 < GRAYSCALE_SATURATION;
+            let b_gray = b.1 < GRAYSCALE_SATURATION;
+            a_gray
+                .cmp(&b_gray)
+                .then(a.0.total_cmp(&b.0))
+                .then(a.1.total_cmp(&b.1))
+                .then(a.2.total_cmp(&b.2))
+        });
+        colors.into_iter().map(|(value, _)| value).collect()
+    }
+
+    /// Returns the unique colors ordered by HSV value (brightness), then saturation, then hue,
+    /// darkest first.
+    pub fn get_colors_sorted_by_value(&mut self) -> Vec<[u8; 3]> {
+        let mut colors: Vec<([u8; 3], (f32, f32, f32))> = self
+            .get_colors()
+            .into_iter()
+            .map(|value| (value, color::rgb_to_hsv(value)))
+            .collect();
+        colors.sort_by(|(a_rgb, a), (b_rgb, b)| {
+            a.2.total_cmp(&b.2)
+                .then(a.1.total_cmp(&b.1))
+                .then(a.0.total_cmp(&b.0))
+                .then(a_rgb.cmp(b_rgb))
+        });
+        colors.into_iter().map(|(value, _)| value).collect()
+    }
+
+    /// Returns the most frequent color of the compressed image.
+    /// Ties are broken by the lexicographically smaller color.
     pub fn get_dominant_color(&mut self) -> [u8; 3] {
-        return match &self.colors {
-            Some(arr) => {
-                let mut f = 0;
-                let mut s = 0;
-                let mut t = 0;
+        HandleImage::most_frequent(self.color_counts())
+    }
 
-                for value in arr {
-                    f += value[0] as u64;
-                    s += value[1] as u64;
-                    t += value[2] as u64;
-                }
-                [
-                    (f as f32 / arr.len() as f32).round() as u8,
-                    (s as f32 / arr.len() as f32).round() as u8,
-                    (t as f32 / arr.len() as f32).round() as u8,
-                ]
+    /// Returns the dominant color if every pixel of the compressed image is within `tolerance`
+    /// of it per channel, flagging blank canvases and placeholders, otherwise `None`.
+    pub fn is_solid_color(&mut self, tolerance: u8) -> Option<[u8; 3]> {
+        let counts = self.color_counts();
+        if counts.is_empty() {
+            return None;
+        }
+        let dominant = HandleImage::most_frequent(counts);
+        counts
+            .keys()
+            .all(|value| HandleImage::within_tolerance(*value, dominant, tolerance))
+            .then_some(dominant)
+    }
+
+    /// Like [`get_dominant_color`](Self::get_dominant_color), but only counts pixels whose
+    /// alpha is at least `alpha_threshold`, so a logo wins over its transparent surround.
+    /// Returns black if no pixel is opaque enough.
+    pub fn get_dominant_color_opaque(&mut self, alpha_threshold: u8) -> [u8; 3] {
+        let mut counts = HashMap::new();
+        for pix in self.compressed_rgba.pixels() {
+            if pix[3] >= alpha_threshold {
+                *counts.entry([pix[0], pix[1], pix[2]]).or_insert(0) += 1;
             }
-            None => {
-                let mut seen = HashSet::new();
-                for pix in self.compressed_image.pixels() {
-                    seen.insert([pix[0], pix[1], pix[2]]);
+        }
+        HandleImage::most_frequent(&counts)
+    }
+
+    /// Derives a color scheme from the dominant color: the dominant color, its complement,
+    /// its two analogous colors 30 degrees apart and its two triadic colors.
+    pub fn generate_scheme(&mut self) -> Vec<[u8; 3]> {
+        let dominant = self.get_dominant_color();
+        let mut scheme = vec![dominant, scheme::complementary(dominant)];
+        scheme.extend(scheme::analogous(dominant, ANALOGOUS_SPREAD));
+        scheme.extend(scheme::triadic(dominant));
+        scheme
+    }
+
+    /// `steps` tints and shades of the dominant color, from dark to light.
+    pub fn get_monochromatic_scheme(&mut self, steps: usize) -> Vec<[u8; 3]> {
+        scheme::monochromatic(self.get_dominant_color(), steps)
+    }
+
+    /// Name of the CSS color closest to the dominant color.
+    pub fn get_dominant_color_name(&mut self) -> &'static str {
+        named_colors::nearest_css_name(self.get_dominant_color())
+    }
+
+    /// Clusters the pixels of the compressed image into at most `k` colors using k-means.
+    /// The centroids are returned most populous cluster first.
+    pub fn get_dominant_colors(&mut self, k: usize) -> Vec<[u8; 3]> {
+        self.kmeans_clusters(k)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    /// Like [`get_dominant_colors`](Self::get_dominant_colors), but clusters in CIELAB, where
+    /// distances follow perceived differences. Clusters then match how people group colors,
+    /// which matters most for subtle differences such as pastels.
+    pub fn get_dominant_colors_lab(&mut self, k: usize) -> Vec<[u8; 3]> {
+        let mut points: Vec<([f32; 3], u32)> = self
+            .color_counts()
+            .iter()
+            .map(|(value, count)| {
+                let (l, a, b) = color::rgb_to_lab(*value);
+                ([l, a, b], *count)
+            })
+            .collect();
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        quantize::kmeans(&points, k)
+            .into_iter()
+            .map(|([l, a, b], _)| color::lab_to_rgb((l, a, b)))
+            .collect()
+    }
+
+    /// Clusters the pixels into at most `n` colors with k-means and returns each color with
+    /// the fraction of the image area assigned to it, largest first. The fractions sum to 1.
+    pub fn get_palette_by_coverage(&mut self, n: usize) -> Vec<([u8; 3], f32)> {
+        let clusters = self.kmeans_clusters(n);
+        let total: u64 = clusters.iter().map(|(_, size)| *size as u64).sum();
+        clusters
+            .into_iter()
+            .map(|(value, size)| (value, size as f32 / total as f32))
+            .collect()
+    }
+
+    fn kmeans_clusters(&mut self, k: usize) -> Vec<([u8; 3], u32)> {
+        let mut points: Vec<([f32; 3], u32)> = self
+            .color_counts()
+            .iter()
+            .map(|(color, count)| ([color[0] as f32, color[1] as f32, color[2] as f32], *count))
+            .collect();
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        quantize::kmeans(&points, k)
+            .into_iter()
+            .map(|(centroid, size)| {
+                (
+                    [
+                        centroid[0].round() as u8,
+                        centroid[1].round() as u8,
+                        centroid[2].round() as u8,
+                    ],
+                    size,
+                )
+            })
+            .collect()
+    }
+
+    /// Builds an `n` color palette by median cut, splitting the frequency-weighted color space
+    /// along its widest channel until `n` buckets exist. Faster than `get_dominant_colors`,
+    /// but the buckets follow axis-aligned cuts, so clusters can be less tight than k-means.
+    pub fn get_palette_median_cut(&mut self, n: usize) -> Vec<[u8; 3]> {
+        let mut colors: Vec<([u8; 3], u32)> =
+            self.color_counts().iter().map(|(k, v)| (*k, *v)).collect();
+        colors.sort();
+        quantize::median_cut(&colors, n)
+            .into_iter()
+            .map(|(color, _)| color)
+            .collect()
+    }
+
+    /// Reduces the image to at most `max_colors` colors with octree quantization, the classic
+    /// GIF palette algorithm. It scales better to large palettes than k-means or median cut.
+    /// Colors are returned most populous leaf first.
+    pub fn get_palette_octree(&mut self, max_colors: usize) -> Vec<[u8; 3]> {
+        let mut colors: Vec<([u8; 3], u32)> =
+            self.color_counts().iter().map(|(k, v)| (*k, *v)).collect();
+        colors.sort();
+        quantize::octree(&colors, max_colors)
+            .into_iter()
+            .map(|(color, _)| color)
+            .collect()
+    }
+
+    /// Builds a palette spanning the image's color variety by farthest-point sampling. The colors
+    /// are first grouped into buckets of their top 4 bits per channel, each represented by the
+    /// pixel-weighted average of its colors, and the 256 most populous buckets become the
+    /// candidates. Seeded with the most populous one, it repeatedly adds whichever candidate has
+    /// the largest Delta E to its nearest already picked color. Bucketing keeps stray noise pixels
+    /// out while still reaching every region of images where no color repeats, such as gradients.
+    pub fn get_diverse_palette(&mut self, n: usize) -> Vec<[u8; 3]> {
+        let shift = 8 - DIVERSE_BITS_PER_CHANNEL;
+        let mut buckets: HashMap<[u8; 3], Vec<([u8; 3], u32)>> = HashMap::new();
+        for (value, count) in self.color_counts() {
+            buckets
+                .entry(value.map(|channel| channel >> shift))
+                .or_default()
+                .push((*value, *count));
+        }
+        let mut averages: Vec<([u8; 3], u32)> = buckets
+            .values()
+            .map(|bucket| quantize::weighted_average(bucket))
+            .collect();
+        averages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let candidates: Vec<([u8; 3], color::Lab)> = averages
+            .into_iter()
+            .take(DIVERSE_CANDIDATES)
+            .map(|(value, _)| (value, color::rgb_to_lab(value)))
+            .collect();
+        let mut nearest = vec![f32::MAX; candidates.len()];
+        let mut palette = vec![];
+        let mut next = 0;
+        while palette.len() < n.min(candidates.len()) {
+            let (value, lab) = candidates[next];
+            palette.push(value);
+            for (distance, (_, other)) in nearest.iter_mut().zip(&candidates) {
+                *distance = distance.min(color::lab_distance(lab, *other));
+            }
+            match nearest
+                .iter()
+                .enumerate()
+                .filter(|(_, distance)| **distance > 0.0)
+                .max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(&a.0)))
+            {
+                Some((i, _)) => next = i,
+                None => break,
+            }
+        }
+        palette
+    }
+
+    /// Greedily merges unique colors whose CIE76 Delta E to a group's most frequent color is
+    /// below `max_delta_e`, returning each group's frequency-weighted average, largest first.
+    pub fn merge_similar_colors(&mut self, max_delta_e: f32) -> Vec<[u8; 3]> {
+        let colors = self.get_top_colors(usize::MAX);
+        let mut groups: Vec<(color::Lab, Vec<_>)> = vec![];
+        for (value, count) in colors {
+            let lab = color::rgb_to_lab(value);
+            match groups
+                .iter_mut()
+                .find(|(seed, _)| color::lab_distance(*seed, lab) < max_delta_e)
+            {
+                Some((_, members)) => members.push((value, count)),
+                None => groups.push((lab, vec![(value, count)])),
+            }
+        }
+        let mut merged: Vec<([u8; 3], u32)> = groups
+            .iter()
+            .map(|(_, members)| quantize::weighted_average(members))
+            .collect();
+        merged.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        merged.into_iter().map(|(value, _)| value).collect()
+    }
+
+    /// Mean RGB over every pixel of the compressed image, so frequent colors weigh more.
+    /// Unlike [`get_dominant_color`](Self::get_dominant_color) this is a blend, the overall
+    /// tint of the image, and may not be a color that actually occurs in it.
+    pub fn get_weighted_average_color(&mut self) -> [u8; 3] {
+        let counts: Vec<([u8; 3], u32)> =
+            self.color_counts().iter().map(|(k, v)| (*k, *v)).collect();
+        quantize::weighted_average(&counts).0
+    }
+
+    /// Like [`get_weighted_average_color`](Self::get_weighted_average_color), but averages in
+    /// linear light and converts back to sRGB, matching how the colors would blend physically.
+    /// Averaging in gamma space comes out too dark: half pure red and half pure green gives
+    /// `[188, 188, 0]` here instead of the muddy `[128, 128, 0]`.
+    pub fn get_average_color_linear(&mut self) -> [u8; 3] {
+        let mut sums = [0.0f64; 3];
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            for (sum, channel) in sums.iter_mut().zip(value) {
+                *sum += color::srgb_to_linear(*channel) as f64 * *count as f64;
+            }
+            total += *count as u64;
+        }
+        sums.map(|sum| color::linear_to_srgb((sum / total.max(1) as f64) as f32))
+    }
+
+    /// Frequency-weighted mean of the `k` most frequent colors only. It sits between
+    /// [`get_dominant_color`](Self::get_dominant_color), which jumps to whichever single color
+    /// wins, and [`get_weighted_average_color`](Self::get_weighted_average_color), which every
+    /// stray pixel pulls on, giving a stable base color for themes.
+    pub fn get_representative_color(&mut self, k: usize) -> [u8; 3] {
+        quantize::weighted_average(&self.get_top_colors(k)).0
+    }
+
+    /// Returns, per channel independently, the value at `channel_percentile` of the
+    /// pixel-weighted distribution using the nearest-rank method. `50.0` gives the per-channel
+    /// median, which unlike the mean is not pulled by outliers such as specular highlights.
+    pub fn get_percentile_color(&mut self, channel_percentile: f32) -> Result<[u8; 3]> {
+        if !(0.0..=100.0).contains(&channel_percentile) {
+            return Err(ColorPaletteError::InvalidArgument(format!(
+                "channel_percentile must be between 0 and 100, got {}",
+                channel_percentile
+            )));
+        }
+        let mut histograms = [[0u64; 256]; 3];
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            for (histogram, channel) in histograms.iter_mut().zip(value) {
+                histogram[*channel as usize] += *count as u64;
+            }
+            total += *count as u64;
+        }
+        let rank = ((channel_percentile as f64 / 100.0 * total as f64).ceil() as u64).max(1);
+        let mut percentile = [0u8; 3];
+        for (result, histogram) in percentile.iter_mut().zip(&histograms) {
+            let mut seen = 0u64;
+            for (channel, count) in histogram.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    *result = channel as u8;
+                    break;
                 }
-                let _ = &self.get_colors();
-                self.get_dominant_color()
             }
+        }
+        Ok(percentile)
+    }
+
+    /// Mean relative luminance over every pixel of the compressed image, in `0.0..=1.0`.
+    pub fn get_average_luminance(&mut self) -> f32 {
+        let mut sum = 0.0f64;
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            sum += color::relative_luminance(*value) as f64 * *count as f64;
+            total += *count as u64;
+        }
+        if total == 0 {
+            return 0.0;
+        }
+        (sum / total as f64) as f32
+    }
+
+    pub fn is_dark(&mut self) -> bool {
+        self.get_average_luminance() < 0.5
+    }
+
+    /// Collects the `n` most frequent colors and their counts into a [`Palette`].
+    pub fn to_palette(&mut self, n: usize) -> Palette {
+        let (colors, counts) = self.get_top_colors(n).into_iter().unzip();
+        Palette {
+            colors,
+            counts,
+            dominant: self.get_dominant_color(),
+        }
+    }
+
+    /// Simulates how the `n` most frequent colors look with the given color vision deficiency.
+    pub fn simulate_palette(&mut self, n: usize, kind: ColorBlindness) -> Vec<[u8; 3]> {
+        self.get_top_colors(n)
+            .into_iter()
+            .map(|(value, _)| color_blindness::simulate_color_blindness(value, kind))
+            .collect()
+    }
+
+    /// Paints the `n` most frequent colors as vertical bars of `swatch_size` pixels,
+    /// most frequent on the left. The image is `swatch_size` high and one swatch wide per color.
+    pub fn render_palette(&mut self, n: usize, swatch_size: u32) -> RgbImage {
+        let palette = self.get_top_colors(n);
+        RgbImage::from_fn(palette.len() as u32 * swatch_size, swatch_size, |x, _| {
+            Rgb(palette[(x / swatch_size) as usize].0)
+        })
+    }
+
+    /// Writes [`render_palette`](Self::render_palette) to `path` as a PNG.
+    pub fn save_palette_png<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        n: usize,
+        swatch_size: u32,
+    ) -> Result<()> {
+        self.render_palette(n, swatch_size)
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(|err| match err {
+                image::ImageError::IoError(err) => ColorPaletteError::Io(err),
+                err => ColorPaletteError::Encode(err),
+            })
+    }
+
+    /// Like [`render_palette`](Self::render_palette), but each swatch is blended with its
+    /// average alpha over a gray checkerboard, so translucent colors show as such.
+    pub fn render_palette_with_alpha(&mut self, n: usize, swatch_size: u32) -> RgbaImage {
+        let palette = self.get_top_colors(n);
+        let mut alphas = vec![(0u64, 0u64); palette.len()];
+        for pix in self.compressed_rgba.pixels() {
+            if let Some(i) = palette
+                .iter()
+                .position(|(value, _)| value[..] == pix.0[..3])
+            {
+                alphas[i].0 += pix[3] as u64;
+                alphas[i].1 += 1;
+            }
+        }
+        let cell = (swatch_size / 4).max(1);
+        RgbaImage::from_fn(palette.len() as u32 * swatch_size, swatch_size, |x, y| {
+            let i = (x / swatch_size) as usize;
+            let alpha = alphas[i].0 as f32 / alphas[i].1.max(1) as f32 / 255.0;
+            let background = match (x / cell + y / cell) % 2 {
+                0 => CHECKER_LIGHT,
+                _ => CHECKER_DARK,
+            } as f32;
+            let blend = |c: u8| (c as f32 * alpha + background * (1.0 - alpha)).round() as u8;
+            let [r, g, b] = palette[i].0;
+            Rgba([blend(r), blend(g), blend(b), 255])
+        })
+    }
+
+    /// Maps every pixel of the full-resolution image to the nearest, by Delta E, of the `n`
+    /// k-means colors of [`get_dominant_colors`](Self::get_dominant_colors), giving a poster
+    /// effect with at most `n` colors.
+    pub fn posterize(&mut self, n: usize) -> RgbImage {
+        let palette: Vec<([u8; 3], color::Lab)> = self
+            .get_dominant_colors(n)
+            .into_iter()
+            .map(|value| (value, color::rgb_to_lab(value)))
+            .collect();
+        let mut nearest: HashMap<[u8; 3], [u8; 3]> = HashMap::new();
+        let mut posterized = self.image.clone();
+        if palette.is_empty() {
+            return posterized;
+        }
+        for pix in posterized.pixels_mut() {
+            pix.0 = *nearest.entry(pix.0).or_insert_with(|| {
+                let lab = color::rgb_to_lab(pix.0);
+                palette
+                    .iter()
+                    .min_by(|a, b| {
+                        color::lab_distance(a.1, lab).total_cmp(&color::lab_distance(b.1, lab))
+                    })
+                    .unwrap()
+                    .0
+            });
+        }
+        posterized
+    }
+
+    /// Returns a thumbnail whose shorter side is at most `thumb_max` together with the `n` most
+    /// frequent colors. When the compressed analysis image already has the thumbnail's size and
+    /// was made by the default Triangle resize without later adjustments, it is reused instead of
+    /// resizing again.
+    pub fn thumbnail_and_palette(&mut self, thumb_max: u32, n: usize) -> (RgbImage, Vec<[u8; 3]>) {
+        let [width, height] = self.get_dimensions();
+        let ratio = (thumb_max as f64 / HandleImage::smaller(width, height) as f64).min(1.0);
+        let size = [
+            HandleImage::calculate(width, ratio),
+            HandleImage::calculate(height, ratio),
+        ];
+        let reusable = !self.compressed_adjusted
+            && !self.options.preserve_colors
+            && self.options.exact_size.is_none()
+            && self.options.filter == FilterType::Triangle;
+        let thumbnail = if reusable && size == self.compressed_dimensions() {
+            self.compressed_image.clone()
+        } else {
+            imageops::resize(&self.image, size[0], size[1], FilterType::Triangle)
         };
+        (thumbnail, self.top_color_values(n))
+    }
+
+    /// Exports the `n` most frequent colors as a GIMP palette (`.gpl`) named `name`.
+    pub fn export_gpl(&mut self, n: usize, name: &str) -> String {
+        export::gpl(&self.top_color_values(n), name)
+    }
+
+    /// Exports the `n` most frequent colors as Adobe Swatch Exchange (`.ase`) bytes.
+    pub fn export_ase(&mut self, n: usize) -> Vec<u8> {
+        export::ase(&self.top_color_values(n))
+    }
+
+    /// Exports the `n` most frequent colors as a `:root` block of CSS custom properties
+    /// named `--{prefix}-1`, `--{prefix}-2`, ...
+    pub fn export_css_variables(&mut self, n: usize, prefix: &str) -> String {
+        export::css_variables(&self.top_color_values(n), prefix)
+    }
+
+    /// Exports the `n` most frequent colors as a `tailwind.config.js` snippet extending
+    /// the theme with a `palette` color.
+    pub fn export_tailwind_config(&mut self, n: usize) -> String {
+        export::tailwind_config(&self.top_color_values(n))
+    }
+
+    /// Checks for grayscale using the stored threshold, 16 unless configured on the builder or
+    /// with [`set_grayscale_threshold`](Self::set_grayscale_threshold).
+    /// Use [`check_grayscale`](Self::check_grayscale) for a one-off threshold.
+    pub fn is_grayscale(&mut self) -> bool {
+        self.check_grayscale(self.grayscale_threshold)
+    }
+
+    /// The threshold used by [`is_grayscale`](Self::is_grayscale).
+    pub fn grayscale_threshold(&self) -> u8 {
+        self.grayscale_threshold
+    }
+
+    /// Changes the threshold used by [`is_grayscale`](Self::is_grayscale).
+    pub fn set_grayscale_threshold(&mut self, threshold: u8) {
+        self.grayscale_threshold = threshold;
     }
 
     pub fn check_grayscale(&mut self, threshold: u8) -> bool {
-        return match &self.colors {
+        match &self.colors {
             Some(arr) => {
                 let mut vec = vec![];
                 for value in arr {
@@ -124,11 +1345,175 @@ impl HandleImage {
                 let _ = &self.get_colors();
                 self.check_grayscale(threshold)
             }
-        };
+        }
+    }
+
+    /// Like `check_grayscale`, but only requires `min_ratio` of the pixels to be within
+    /// `threshold`, so a few stray colored pixels such as JPEG artifacts are tolerated.
+    pub fn check_grayscale_ratio(&mut self, threshold: u8, min_ratio: f32) -> bool {
+        let mut gray = 0u64;
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            if HandleImage::get_difference(value[0], value[1]) < threshold
+                && HandleImage::get_difference(value[1], value[2]) < threshold
+                && HandleImage::get_difference(value[0], value[2]) < threshold
+            {
+                gray += *count as u64;
+            }
+            total += *count as u64;
+        }
+        total > 0 && gray as f64 >= total as f64 * min_ratio as f64
     }
 
+    /// Counts the pixels of the compressed image in `bins` equal HSL hue ranges covering
+    /// 0-360 degrees. Grayscale pixels have no meaningful hue and are left out.
+    pub fn get_hue_histogram(&mut self, bins: usize) -> Vec<u32> {
+        let mut histogram = vec![0u32; bins];
+        if bins == 0 {
+            return histogram;
+        }
+        for (value, count) in self.color_counts() {
+            let (h, s, _) = color::rgb_to_hsl(*value);
+            if s < GRAYSCALE_SATURATION {
+                continue;
+            }
+            let bin = ((h / 360.0 * bins as f32) as usize).min(bins - 1);
+            histogram[bin] += count;
+        }
+        histogram
+    }
+
+    /// Pixel-weighted mean HSL saturation of the compressed image, in `0.0..=1.0`.
+    pub fn get_average_saturation(&mut self) -> f32 {
+        let mut sum = 0.0f64;
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            sum += color::rgb_to_hsl(*value).1 as f64 * *count as f64;
+            total += *count as u64;
+        }
+        if total == 0 {
+            return 0.0;
+        }
+        (sum / total as f64) as f32
+    }
+
+    /// Highest HSL saturation of any pixel of the compressed image.
+    pub fn get_max_saturation(&mut self) -> f32 {
+        self.color_counts()
+            .keys()
+            .map(|value| color::rgb_to_hsl(*value).1)
+            .fold(0.0, f32::max)
+    }
+
+    /// Returns the most saturated color among those covering at least `min_count` pixels,
+    /// preferring the lighter one on ties. `min_count` skips stray noise pixels.
+    /// Falls back to the dominant color if no color is frequent enough.
+    pub fn get_most_vivid_color(&mut self, min_count: u32) -> [u8; 3] {
+        let counts = self.color_counts();
+        counts
+            .iter()
+            .filter(|(_, count)| **count >= min_count)
+            .map(|(value, _)| (*value, color::rgb_to_hsl(*value)))
+            .max_by(|(a, a_hsl), (b, b_hsl)| {
+                a_hsl
+                    .1
+                    .total_cmp(&b_hsl.1)
+                    .then(a_hsl.2.total_cmp(&b_hsl.2))
+                    .then(b.cmp(a))
+            })
+            .map(|(value, _)| value)
+            .unwrap_or_else(|| Self::most_frequent(counts))
+    }
+
+    /// Hasler and Süsstrunk's colorfulness metric over the compressed image: the spread plus
+    /// 0.3 times the mean of the opponent channels `R - G` and `(R + G) / 2 - B`, on the 0-255
+    /// scale. Grayscale images score 0, around 30 is moderately colorful and vivid rainbows
+    /// exceed 100.
+    pub fn colorfulness(&mut self) -> f32 {
+        let mut sums = [0.0f64; 2];
+        let mut squares = [0.0f64; 2];
+        let mut total = 0.0f64;
+        for (value, count) in self.color_counts() {
+            let [r, g, b] = value.map(|channel| channel as f64);
+            let count = *count as f64;
+            for (i, opponent) in [r - g, 0.5 * (r + g) - b].into_iter().enumerate() {
+                sums[i] += opponent * count;
+                squares[i] += opponent * opponent * count;
+            }
+            total += count;
+        }
+        if total == 0.0 {
+            return 0.0;
+        }
+        let mean = sums.map(|sum| sum / total);
+        let variance = [0, 1].map(|i| (squares[i] / total - mean[i] * mean[i]).max(0.0));
+        let spread = (variance[0] + variance[1]).sqrt();
+        let center = (mean[0] * mean[0] + mean[1] * mean[1]).sqrt();
+        (spread + 0.3 * center) as f32
+    }
+
+    /// Whether the average saturation exceeds `threshold`.
+    pub fn is_vibrant(&mut self, threshold: f32) -> bool {
+        self.get_average_saturation() > threshold
+    }
+
+    /// Signed warmth score in `-1.0..=1.0`: positive when reds, oranges and yellows dominate,
+    /// negative when greens and blues do. Every pixel is weighted by its chroma, so grays
+    /// and washed-out colors barely move the score.
+    pub fn get_color_temperature(&mut self) -> f32 {
+        let mut score = 0.0f64;
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            let (h, s, l) = color::rgb_to_hsl(*value);
+            let chroma = s * (1.0 - (2.0 * l - 1.0).abs());
+            let sign = if h >= WARM_HUE.0 || h < WARM_HUE.1 {
+                1.0
+            } else if (COOL_HUE.0..COOL_HUE.1).contains(&h) {
+                -1.0
+            } else {
+                0.0
+            };
+            score += sign * chroma as f64 * *count as f64;
+            total += *count as u64;
+        }
+        if total == 0 {
+            return 0.0;
+        }
+        (score / total as f64) as f32
+    }
+
+    pub fn is_warm(&mut self) -> bool {
+        self.get_color_temperature() > 0.0
+    }
+
+    /// Checks whether the image is sepia toned: at least 90% of the tinted unique colors need a
+    /// warm brown hue (20-50 degrees, widened by `tolerance` degrees on both sides) with low to
+    /// moderate saturation. Saturation is measured as HSL chroma so that near-black and
+    /// near-white browns still count. Neutral grays are ignored, so a grayscale image is not sepia.
+    pub fn check_sepia(&mut self, tolerance: u8) -> bool {
+        let min_hue = SEPIA_HUE.0 - tolerance as f32;
+        let max_hue = SEPIA_HUE.1 + tolerance as f32;
+        let mut tinted = 0;
+        let mut sepia = 0;
+        for value in self.color_counts().keys() {
+            let (h, s, l) = color::rgb_to_hsl(*value);
+            let chroma = s * (1.0 - (2.0 * l - 1.0).abs());
+            if chroma < SEPIA_NEUTRAL_CHROMA {
+                continue;
+            }
+            tinted += 1;
+            if h >= min_hue && h <= max_hue && chroma <= SEPIA_MAX_CHROMA {
+                sepia += 1;
+            }
+        }
+        tinted > 0 && sepia as f32 >= tinted as f32 * SEPIA_MIN_RATIO
+    }
+
+    /// Largest difference between two channels of any unique color; `check_grayscale` passes
+    /// for any threshold above it. A single noisy pixel raises it, see
+    /// [`suggest_grayscale_threshold`](Self::suggest_grayscale_threshold) for photos and JPEGs.
     pub fn get_grayscale_threshold(&mut self) -> Option<u8> {
-        return match &self.colors {
+        match &self.colors {
             Some(arr) => {
                 let mut vec = vec![];
                 for value in arr {
@@ -136,22 +1521,158 @@ impl HandleImage {
                     vec.push(HandleImage::get_difference(value[0], value[2]));
                     vec.push(HandleImage::get_difference(value[1], value[2]));
                 }
-                match vec.iter().max_by_key(|x| x.clone()) {
-                    Some(v) => Some(*v),
-                    None => None,
-                }
+                vec.iter().max().copied()
             }
             None => {
                 let _ = &self.get_colors();
                 self.get_grayscale_threshold()
             }
-        };
+        }
+    }
+
+    /// Like [`get_grayscale_threshold`](Self::get_grayscale_threshold), but returns the largest
+    /// channel difference at `percentile` of the pixel-weighted distribution using the
+    /// nearest-rank method, so `99.0` ignores the most colorful 1% of pixels such as compression
+    /// noise. Use the strict maximum for synthetic images where any color counts.
+    /// `percentile` is clamped to `0.0..=100.0`.
+    pub fn suggest_grayscale_threshold(&mut self, percentile: f32) -> u8 {
+        let mut histogram = [0u64; 256];
+        let mut total = 0u64;
+        for (value, count) in self.color_counts() {
+            let spread = HandleImage::get_difference(value[0], value[1])
+                .max(HandleImage::get_difference(value[0], value[2]))
+                .max(HandleImage::get_difference(value[1], value[2]));
+            histogram[spread as usize] += *count as u64;
+            total += *count as u64;
+        }
+        let rank =
+            ((percentile.clamp(0.0, 100.0) as f64 / 100.0 * total as f64).ceil() as u64).max(1);
+        let mut seen = 0u64;
+        for (spread, count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return spread as u8;
+            }
+        }
+        0
+    }
+
+    /// 64-bit difference hash (dHash) of the image: it is shrunk to 9x8 grayscale pixels and
+    /// each bit records whether a pixel is brighter than its right neighbour. Unlike the palette
+    /// it follows the image's structure; compare hashes with [`hamming_distance`], where
+    /// near-identical images differ in only a few bits.
+    pub fn perceptual_hash(&self) -> u64 {
+        let small = DynamicImage::ImageRgb8(imageops::resize(
+            &self.image,
+            PHASH_WIDTH + 1,
+            PHASH_HEIGHT,
+            FilterType::Triangle,
+        ))
+        .into_luma8();
+        let mut hash = 0u64;
+        for y in 0..PHASH_HEIGHT {
+            for x in 0..PHASH_WIDTH {
+                let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+                hash = hash << 1 | brighter as u64;
+            }
+        }
+        hash
     }
 
     pub fn get_dimensions(&self) -> [u32; 2] {
         [self.image.width(), self.image.height()]
     }
 
+    /// The downscaled copy of the image that all color analysis runs on.
+    pub fn compressed(&self) -> &RgbImage {
+        &self.compressed_image
+    }
+
+    pub fn compressed_dimensions(&self) -> [u32; 2] {
+        [
+            self.compressed_image.width(),
+            self.compressed_image.height(),
+        ]
+    }
+
+    fn color_counts(&mut self) -> &HashMap<[u8; 3], u32> {
+        if self.color_counts.is_none() {
+            self.color_counts = Some(HandleImage::count_pixels(&self.compressed_image));
+        }
+        self.color_counts.as_ref().unwrap()
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    fn count_pixels(image: &RgbImage) -> HashMap<[u8; 3], u32> {
+        HandleImage::count_pixels_serial(image)
+    }
+
+    #[cfg(any(not(feature = "rayon"), test))]
+    fn count_pixels_serial(image: &RgbImage) -> HashMap<[u8; 3], u32> {
+        let mut counts = HashMap::new();
+        for pix in image.pixels() {
+            *counts.entry([pix[0], pix[1], pix[2]]).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[cfg(feature = "rayon")]
+    fn count_pixels(image: &RgbImage) -> HashMap<[u8; 3], u32> {
+        use rayon::prelude::*;
+
+        image
+            .as_raw()
+            .par_chunks(3 * PARALLEL_CHUNK_PIXELS)
+            .fold(HashMap::new, |mut counts, chunk| {
+                for pix in chunk.chunks_exact(3) {
+                    *counts.entry([pix[0], pix[1], pix[2]]).or_insert(0) += 1;
+                }
+                counts
+            })
+            .reduce(HashMap::new, |mut merged, counts| {
+                for (value, count) in counts {
+                    *merged.entry(value).or_insert(0) += count;
+                }
+                merged
+            })
+    }
+
+    fn top_color_values(&mut self, n: usize) -> Vec<[u8; 3]> {
+        self.get_top_colors(n)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    /// The `n` most frequent colors passing `pred` with their counts, most frequent first and
+    /// ties going to the lexicographically smaller color.
+    fn top_n_where(&mut self, pred: impl Fn(&[u8; 3]) -> bool, n: usize) -> Vec<([u8; 3], u32)> {
+        let mut counts: Vec<([u8; 3], u32)> = self
+            .color_counts()
+            .iter()
+            .filter(|(value, _)| pred(value))
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Whether every channel of `a` is within `tolerance` of the same channel of `b`.
+    fn within_tolerance(a: [u8; 3], b: [u8; 3], tolerance: u8) -> bool {
+        a.iter()
+            .zip(b)
+            .all(|(a, b)| HandleImage::get_difference(*a, b) <= tolerance)
+    }
+
+    fn most_frequent(counts: &HashMap<[u8; 3], u32>) -> [u8; 3] {
+        counts
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(color, _)| *color)
+            .unwrap_or([0, 0, 0])
+    }
+
     fn get_difference(f: u8, s: u8) -> u8 {
         if f < s {
             return s - f;
@@ -170,3 +1691,690 @@ impl HandleImage {
         (u as f64 * f).round() as u32
     }
 }
+
+/// Reads the width and height of the image at `src` from its header, without decoding the
+/// pixels. Use it to skip oversized images before paying for a full load.
+pub fn dimensions_only(src: &str) -> Result<[u32; 2]> {
+    let (width, height) = image::io::Reader::open(src)?
+        .with_guessed_format()?
+        .into_dimensions()?;
+    Ok([width, height])
+}
+
+/// Scores how alike two images' palettes are, from `0.0` to `1.0` where `1.0` means
+/// near-identical palettes. Each of the `n` k-means colors of `a` is matched to the nearest of
+/// `b`'s by Delta E, scored linearly down to zero at a Delta E of 50, and the scores are averaged
+/// weighted by the color's coverage of `a`. Swap the arguments to match `b` against `a`.
+pub fn palette_similarity(a: &mut HandleImage, b: &mut HandleImage, n: usize) -> f32 {
+    let others: Vec<color::Lab> = b
+        .get_dominant_colors(n)
+        .into_iter()
+        .map(color::rgb_to_lab)
+        .collect();
+    a.get_palette_by_coverage(n)
+        .into_iter()
+        .map(|(value, coverage)| {
+            let lab = color::rgb_to_lab(value);
+            let nearest = others
+                .iter()
+                .map(|other| color::lab_distance(lab, *other))
+                .fold(f32::MAX, f32::min);
+            coverage * (1.0 - nearest / SIMILARITY_MAX_DELTA_E).max(0.0)
+        })
+        .sum::<f32>()
+        .min(1.0)
+}
+
+/// Number of differing bits between two [`HandleImage::perceptual_hash`] values; 0 means the
+/// same hash and up to about 10 usually means the same picture.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+impl fmt::Debug for HandleImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unique_colors = match (&self.colors, &self.color_counts) {
+            (Some(colors), _) => Some(colors.len()),
+            (None, Some(counts)) => Some(counts.len()),
+            (None, None) => None,
+        };
+        let mut debug = f.debug_struct("HandleImage");
+        debug.field("dimensions", &self.get_dimensions());
+        match unique_colors {
+            Some(count) => debug.field("unique_colors", &count),
+            None => debug.field("unique_colors", &format_args!("<uncomputed>")),
+        };
+        match &self.color_counts {
+            Some(counts) => debug.field(
+                "dominant",
+                &format_args!("{}", color::to_hex(HandleImage::most_frequent(counts))),
+            ),
+            None => debug.field("dominant", &format_args!("<uncomputed>")),
+        };
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(image: RgbImage) -> HandleImage {
+        HandleImageBuilder::new()
+            .filter(FilterType::Nearest)
+            .from_dynamic_image(DynamicImage::ImageRgb8(image))
+    }
+
+    fn split(width: u32, height: u32, split_x: u32, left: [u8; 3], right: [u8; 3]) -> HandleImage {
+        handle(RgbImage::from_fn(width, height, |x, _| {
+            Rgb(if x < split_x { left } else { right })
+        }))
+    }
+
+    /// A `size` by `size` image of `background` with `[min_x, min_y, max_x, max_y]` (inclusive)
+    /// filled with `subject`.
+    fn framed(size: u32, bounds: [u32; 4], subject: [u8; 3], background: [u8; 3]) -> HandleImage {
+        let [min_x, min_y, max_x, max_y] = bounds;
+        handle(RgbImage::from_fn(size, size, |x, y| {
+            let inside = (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y);
+            Rgb(if inside { subject } else { background })
+        }))
+    }
+
+    #[test]
+    fn reload_replaces_image_and_caches() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!(
+            "image_colorpalette-reload-a-{}.png",
+            std::process::id()
+        ));
+        let second = dir.join(format!(
+            "image_colorpalette-reload-b-{}.png",
+            std::process::id()
+        ));
+        RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]))
+            .save(&first)
+            .unwrap();
+        RgbImage::from_pixel(6, 2, Rgb([40, 50, 60]))
+            .save(&second)
+            .unwrap();
+        let mut image = HandleImage::set_from_path(&first).unwrap();
+        image.set_grayscale_threshold(3);
+        assert_eq!(image.get_dominant_color(), [10, 20, 30]);
+        image.reload(&second).unwrap();
+        assert_eq!(image.get_dimensions(), [6, 2]);
+        assert_eq!(image.get_dominant_color(), [40, 50, 60]);
+        assert_eq!(image.grayscale_threshold(), 3);
+        assert!(image.reload(dir.join("missing.png")).is_err());
+        assert_eq!(image.get_dimensions(), [6, 2]);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn reload_reuses_buffers_and_matches_fresh_load() {
+        let dir = std::env::temp_dir();
+        let paths = ["rgb", "rgba", "gray"].map(|name| {
+            dir.join(format!(
+                "image_colorpalette-reuse-{}-{}.png",
+                name,
+                std::process::id()
+            ))
+        });
+        RgbImage::from_fn(40, 30, |x, y| Rgb([x as u8 * 6, y as u8 * 8, 90]))
+            .save(&paths[0])
+            .unwrap();
+        RgbaImage::from_fn(40, 30, |x, y| Rgba([y as u8 * 8, 70, x as u8 * 6, 200]))
+            .save(&paths[1])
+            .unwrap();
+        image::GrayImage::from_fn(40, 30, |x, y| image::Luma([(x + y) as u8 * 3]))
+            .save(&paths[2])
+            .unwrap();
+        let options = HandleImageBuilder::new().max_dimension(10);
+        let mut image = options.from_path(&paths[0]).unwrap();
+        let buffers = |image: &HandleImage| {
+            (
+                image.image.as_raw().as_ptr(),
+                image.compressed_image.as_raw().as_ptr(),
+                image.compressed_rgba.as_raw().as_ptr(),
+            )
+        };
+        let before = buffers(&image);
+        for path in paths.iter().chain(&paths) {
+            image.get_dominant_color();
+            image.reload(path).unwrap();
+            let fresh = options.from_path(path).unwrap();
+            assert_eq!(image.image, fresh.image);
+            assert_eq!(image.compressed_image, fresh.compressed_image);
+            assert_eq!(image.compressed_rgba, fresh.compressed_rgba);
+            assert!(image.color_counts.is_none());
+            assert_eq!(buffers(&image), before);
+        }
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn thumbnail_ignores_adjusted_analysis_image() {
+        let mut image = HandleImageBuilder::new()
+            .max_dimension(100)
+            .from_dynamic_image(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+                250,
+                200,
+                Rgb([100, 100, 100]),
+            )));
+        let (thumbnail, _) = image.thumbnail_and_palette(100, 1);
+        assert_eq!(thumbnail.dimensions(), (125, 100));
+        image.adjust_brightness(2.0);
+        let (thumbnail, palette) = image.thumbnail_and_palette(100, 1);
+        assert_eq!(thumbnail.dimensions(), (125, 100));
+        assert!(thumbnail.pixels().all(|pix| pix.0 == [100, 100, 100]));
+        assert_eq!(palette, vec![[200, 200, 200]]);
+    }
+
+    #[test]
+    fn thumbnail_ignores_non_default_analysis_image() {
+        let mut image = HandleImageBuilder::new()
+            .max_dimension(100)
+            .filter(FilterType::Nearest)
+            .from_dynamic_image(DynamicImage::ImageRgb8(RgbImage::from_fn(
+                250,
+                200,
+                |x, _| Rgb([x as u8, 0, 0]),
+            )));
+        let (thumbnail, _) = image.thumbnail_and_palette(100, 1);
+        let expected = imageops::resize(&image.image, 125, 100, FilterType::Triangle);
+        assert_eq!(thumbnail, expected);
+    }
+
+    #[test]
+    fn dominant_color_is_most_frequent() {
+        let mut image = handle(RgbImage::from_fn(10, 10, |x, y| {
+            Rgb(if x == 0 && y < 3 {
+                [0, 0, 255]
+            } else {
+                [200, 10, 10]
+            })
+        }));
+        assert_eq!(image.get_dominant_color(), [200, 10, 10]);
+    }
+
+    #[test]
+    fn dominant_color_tie_prefers_smaller_color() {
+        let mut image = split(10, 10, 5, [9, 0, 0], [1, 200, 200]);
+        assert_eq!(image.get_dominant_color(), [1, 200, 200]);
+    }
+
+    #[test]
+    fn colors_sorted_by_hue_put_gray_last() {
+        let colors = [[128, 128, 128], [0, 0, 255], [0, 255, 0], [255, 0, 0]];
+        let mut image = handle(RgbImage::from_fn(4, 1, |x, _| Rgb(colors[x as usize])));
+        assert_eq!(
+            image.get_colors_sorted_by_hue(),
+            vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [128, 128, 128]]
+        );
+    }
+
+    #[test]
+    fn median_cut_returns_n_colors_on_gradient() {
+        let mut image = handle(RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([x as u8 * 4, y as u8 * 4, 128])
+        }));
+        for n in [1, 2, 5, 8, 13] {
+            let palette = image.get_palette_median_cut(n);
+            assert_eq!(palette.len(), n);
+            let unique: HashSet<[u8; 3]> = palette.iter().copied().collect();
+            assert_eq!(unique.len(), n);
+        }
+    }
+
+    #[test]
+    fn render_palette_draws_dominant_color_first() {
+        let mut image = split(10, 10, 7, [200, 10, 10], [10, 10, 200]);
+        let rendered = image.render_palette(2, 8);
+        assert_eq!(rendered.dimensions(), (16, 8));
+        for y in 0..8 {
+            assert_eq!(rendered.get_pixel(0, y).0, [200, 10, 10]);
+            assert_eq!(rendered.get_pixel(15, y).0, [10, 10, 200]);
+        }
+        assert_eq!(image.render_palette(5, 4).dimensions(), (8, 4));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_count_matches_serial_count() {
+        // Several parallel chunks, the last one partial.
+        let image = RgbImage::from_fn(300, 211, |x, y| {
+            Rgb([(x % 7) as u8 * 30, (y % 5) as u8 * 50, ((x + y) % 3) as u8])
+        });
+        assert!(image.pixels().len() > 3 * PARALLEL_CHUNK_PIXELS);
+        assert_eq!(
+            HandleImage::count_pixels(&image),
+            HandleImage::count_pixels_serial(&image)
+        );
+    }
+
+    /// Compares both counting paths on a 12 megapixel photo-sized image. Run it in release
+    /// mode with `cargo test --release --features rayon -- --ignored --nocapture`.
+    #[cfg(feature = "rayon")]
+    #[test]
+    #[ignore]
+    fn parallel_count_timing() {
+        use std::time::Instant;
+        let image = RgbImage::from_fn(4000, 3000, |x, y| {
+            Rgb([(x % 251) as u8, (y % 241) as u8, ((x * y) % 239) as u8])
+        });
+        let start = Instant::now();
+        let serial = HandleImage::count_pixels_serial(&image);
+        let serial_time = start.elapsed();
+        let start = Instant::now();
+        let parallel = HandleImage::count_pixels(&image);
+        let parallel_time = start.elapsed();
+        assert_eq!(parallel, serial);
+        eprintln!(
+            "serial {:?}, parallel {:?} on {} threads",
+            serial_time,
+            parallel_time,
+            rayon::current_num_threads()
+        );
+    }
+
+    #[test]
+    fn check_sepia_accepts_brown_tint_only() {
+        let mut sepia = handle(RgbImage::from_fn(256, 4, |x, _| {
+            let v = x as f32;
+            Rgb([v as u8, (v * 0.85) as u8, (v * 0.65) as u8])
+        }));
+        assert!(sepia.check_sepia(0));
+        let mut gray = handle(RgbImage::from_fn(256, 4, |x, _| Rgb([x as u8; 3])));
+        assert!(!gray.check_sepia(0));
+        let mut colorful = handle(RgbImage::from_fn(360, 4, |x, _| {
+            Rgb(color::hsl_to_rgb(x as f32, 1.0, 0.5))
+        }));
+        assert!(!colorful.check_sepia(10));
+    }
+
+    #[test]
+    fn color_temperature_of_red_and_blue() {
+        let mut red = handle(RgbImage::from_pixel(8, 8, Rgb([255, 0, 0])));
+        assert!((red.get_color_temperature() - 1.0).abs() < 1e-6);
+        assert!(red.is_warm());
+        let mut blue = handle(RgbImage::from_pixel(8, 8, Rgb([0, 0, 255])));
+        assert!((blue.get_color_temperature() + 1.0).abs() < 1e-6);
+        assert!(!blue.is_warm());
+    }
+
+    #[test]
+    fn neutral_brightness_and_gamma_change_nothing() {
+        let mut image = handle(RgbImage::from_fn(16, 16, |x, y| {
+            Rgb([x as u8 * 16, y as u8 * 16, (x * y) as u8])
+        }));
+        let original = image.compressed().clone();
+        image.adjust_brightness(1.0);
+        assert_eq!(image.compressed(), &original);
+        image.adjust_gamma(1.0);
+        assert_eq!(image.compressed(), &original);
+        image.adjust_brightness(0.5);
+        assert_ne!(image.compressed(), &original);
+    }
+
+    #[test]
+    fn hue_histogram_of_red_and_blue_halves() {
+        let mut image = split(10, 10, 4, [255, 0, 0], [0, 0, 255]);
+        let histogram = image.get_hue_histogram(12);
+        assert_eq!(histogram[0], 40);
+        assert_eq!(histogram[8], 60);
+        assert_eq!(histogram.iter().filter(|count| **count > 0).count(), 2);
+        let mut gray = handle(RgbImage::from_pixel(4, 4, Rgb([90, 90, 90])));
+        assert_eq!(gray.get_hue_histogram(12), vec![0; 12]);
+    }
+
+    #[test]
+    fn weighted_average_leans_to_majority_color() {
+        let mut image = split(10, 10, 9, [200, 0, 0], [0, 0, 100]);
+        assert_eq!(image.get_weighted_average_color(), [180, 0, 10]);
+    }
+
+    #[test]
+    fn most_vivid_color_finds_small_orange_patch() {
+        let mut image = handle(RgbImage::from_fn(20, 20, |x, y| {
+            Rgb(if x < 3 && y < 3 {
+                [255, 140, 0]
+            } else {
+                [128, 128, 128]
+            })
+        }));
+        assert_eq!(image.get_dominant_color(), [128, 128, 128]);
+        assert_eq!(image.get_most_vivid_color(1), [255, 140, 0]);
+        assert_eq!(image.get_most_vivid_color(10), [128, 128, 128]);
+    }
+
+    #[test]
+    fn color_bounds_of_a_square() {
+        let image = framed(20, [3, 5, 9, 12], [220, 30, 30], [240, 240, 240]);
+        assert_eq!(image.color_bounds([220, 30, 30], 0), Some([3, 5, 9, 12]));
+        assert_eq!(image.color_bounds([210, 40, 25], 10), Some([3, 5, 9, 12]));
+        assert_eq!(image.color_bounds([210, 40, 25], 9), None);
+        assert_eq!(image.color_bounds([240, 240, 240], 0), Some([0, 0, 19, 19]));
+    }
+
+    #[test]
+    fn representative_color_blends_top_colors() {
+        let mut image = split(10, 10, 7, [200, 0, 0], [0, 0, 100]);
+        assert_eq!(image.get_representative_color(1), [200, 0, 0]);
+        assert_eq!(image.get_representative_color(2), [140, 0, 30]);
+        assert_eq!(image.get_representative_color(5), [140, 0, 30]);
+    }
+
+    #[test]
+    fn suggested_threshold_ignores_rare_colored_pixels() {
+        let mut image = handle(RgbImage::from_fn(10, 10, |x, y| {
+            Rgb(if y == 0 && x < 3 {
+                [200, 40, 40]
+            } else {
+                [100, 102, 101]
+            })
+        }));
+        assert_eq!(image.get_grayscale_threshold(), Some(160));
+        assert_eq!(image.suggest_grayscale_threshold(95.0), 2);
+        assert_eq!(image.suggest_grayscale_threshold(100.0), 160);
+    }
+
+    #[test]
+    fn background_color_ignores_large_centered_subject() {
+        let mut image = framed(20, [2, 2, 17, 17], [30, 90, 200], [250, 245, 240]);
+        assert_eq!(image.get_dominant_color(), [30, 90, 200]);
+        assert_eq!(image.get_background_color(0), [250, 245, 240]);
+    }
+
+    #[test]
+    fn foreground_palette_excludes_white_background() {
+        let mut image = handle(RgbImage::from_fn(20, 20, |x, y| {
+            Rgb(match (x, y) {
+                (5..=9, 5..=14) => [220, 20, 20],
+                (10..=14, 5..=14) => [120, 10, 10],
+                (0, 0) => [250, 252, 248],
+                _ => [255, 255, 255],
+            })
+        }));
+        assert_eq!(image.get_dominant_color(), [255, 255, 255]);
+        assert_eq!(
+            image.get_foreground_palette(3, 8),
+            vec![[120, 10, 10], [220, 20, 20]]
+        );
+    }
+
+    #[test]
+    fn palette_similarity_of_self_and_inverse() {
+        let gradient = |x: u32, y: u32| [x as u8 * 8, y as u8 * 8, 255 - x as u8 * 4];
+        let mut image = handle(RgbImage::from_fn(32, 32, |x, y| Rgb(gradient(x, y))));
+        let mut same = handle(RgbImage::from_fn(32, 32, |x, y| Rgb(gradient(x, y))));
+        let mut inverse = handle(RgbImage::from_fn(32, 32, |x, y| {
+            Rgb(gradient(x, y).map(|channel| 255 - channel))
+        }));
+        assert!(palette_similarity(&mut image, &mut same, 8) > 0.99);
+        let score = palette_similarity(&mut image, &mut inverse, 8);
+        assert!(score < 0.5, "inverse scored {}", score);
+    }
+
+    #[test]
+    fn posterize_uses_at_most_n_colors() {
+        let mut image = handle(RgbImage::from_fn(40, 30, |x, y| {
+            Rgb([x as u8 * 6, y as u8 * 8, (x + y) as u8 * 3])
+        }));
+        for n in [1, 3, 6] {
+            let posterized = image.posterize(n);
+            assert_eq!(posterized.dimensions(), (40, 30));
+            let colors: HashSet<[u8; 3]> = posterized.pixels().map(|pix| pix.0).collect();
+            assert!(colors.len() <= n, "{} colors for n = {}", colors.len(), n);
+        }
+    }
+
+    #[test]
+    fn clone_keeps_independent_cache() {
+        let mut image = split(10, 10, 6, [200, 100, 50], [20, 40, 60]);
+        assert_eq!(image.get_dominant_color(), [200, 100, 50]);
+        let mut copy = image.clone();
+        copy.adjust_brightness(0.5);
+        assert_eq!(copy.get_dominant_color(), [100, 50, 25]);
+        assert_eq!(image.get_dominant_color(), [200, 100, 50]);
+        assert_eq!(
+            image.get_colors(),
+            HashSet::from([[200, 100, 50], [20, 40, 60]])
+        );
+    }
+
+    #[test]
+    fn solid_color_detection() {
+        let mut solid = handle(RgbImage::from_pixel(8, 8, Rgb([12, 200, 80])));
+        assert_eq!(solid.is_solid_color(0), Some([12, 200, 80]));
+        let mut noisy = split(8, 8, 7, [12, 200, 80], [14, 198, 81]);
+        assert_eq!(noisy.is_solid_color(1), None);
+        assert_eq!(noisy.is_solid_color(2), Some([12, 200, 80]));
+        let mut varied = handle(RgbImage::from_fn(8, 8, |x, y| {
+            Rgb([x as u8 * 30, y as u8 * 30, 0])
+        }));
+        assert_eq!(varied.is_solid_color(10), None);
+    }
+
+    #[test]
+    fn diverse_palette_spans_color_regions() {
+        let mut image = handle(RgbImage::from_fn(40, 10, |x, y| {
+            Rgb(match x {
+                0..=24 => [200 + (x + y) as u8 % 4 * 10, 20, 20],
+                25..=29 => [20, 40, 220],
+                30..=34 => [30, 180, 40],
+                _ => [240, 220, 30],
+            })
+        }));
+        assert!(image
+            .get_top_colors(4)
+            .iter()
+            .all(|(value, _)| value[0] >= 200));
+        let palette = image.get_diverse_palette(4);
+        assert_eq!(palette.len(), 4);
+        let hue_bins: HashSet<usize> = palette
+            .iter()
+            .map(|value| (color::rgb_to_hsl(*value).0 / 30.0) as usize)
+            .collect();
+        assert_eq!(hue_bins.len(), 4, "{:?}", palette);
+        for region in [[20, 40, 220], [30, 180, 40], [240, 220, 30]] {
+            assert!(palette.contains(&region), "{:?} missing", region);
+        }
+    }
+
+    #[test]
+    fn save_palette_png_writes_valid_png() {
+        let dir =
+            std::env::temp_dir().join(format!("image_colorpalette-palette-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("palette.png");
+        let mut image = split(10, 10, 7, [200, 10, 10], [10, 10, 200]);
+        image.save_palette_png(&path, 2, 8).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(image::guess_format(&bytes).unwrap(), ImageFormat::Png);
+        let saved = image::load_from_memory(&bytes).unwrap().into_rgb8();
+        assert_eq!(saved, image.render_palette(2, 8));
+        assert!(matches!(
+            image.save_palette_png(dir.join("missing/palette.png"), 2, 8),
+            Err(ColorPaletteError::Io(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn linear_average_is_brighter_than_naive() {
+        let mut image = split(10, 10, 5, [255, 0, 0], [0, 255, 0]);
+        assert_eq!(image.get_average_color_linear(), [188, 188, 0]);
+        assert_eq!(image.get_weighted_average_color(), [128, 128, 0]);
+    }
+
+    #[test]
+    fn palette_excluding_drops_background() {
+        let mut image = handle(RgbImage::from_fn(20, 10, |x, y| {
+            Rgb(match (x, y) {
+                (0..=3, _) => [200, 30, 30],
+                (4..=5, _) => [30, 30, 200],
+                (6, 0) => [2, 250, 4],
+                _ => [0, 255, 0],
+            })
+        }));
+        assert_eq!(image.get_top_colors(1)[0].0, [0, 255, 0]);
+        assert_eq!(
+            image.get_palette_excluding(&[[0, 255, 0]], 8, 3),
+            vec![[200, 30, 30], [30, 30, 200]]
+        );
+        assert_eq!(
+            image.get_palette_excluding(&[[0, 255, 0]], 0, 3),
+            vec![[200, 30, 30], [30, 30, 200], [2, 250, 4]]
+        );
+    }
+
+    #[test]
+    fn colorfulness_of_gray_and_rainbow() {
+        let mut gray = handle(RgbImage::from_fn(64, 8, |x, _| Rgb([x as u8 * 4; 3])));
+        assert!(gray.colorfulness() < 1e-3);
+        let mut rainbow = handle(RgbImage::from_fn(360, 8, |x, _| {
+            Rgb(color::hsl_to_rgb(x as f32, 1.0, 0.5))
+        }));
+        assert!(rainbow.colorfulness() > 100.0);
+    }
+
+    #[test]
+    fn dominant_opaque_color_skips_transparent_background() {
+        let mut image = HandleImageBuilder::new()
+            .filter(FilterType::Nearest)
+            .from_dynamic_image(DynamicImage::ImageRgba8(RgbaImage::from_fn(
+                20,
+                20,
+                |x, y| {
+                    Rgba(if (6..14).contains(&x) && (6..14).contains(&y) {
+                        [230, 120, 20, 255]
+                    } else {
+                        [0, 0, 0, 0]
+                    })
+                },
+            )));
+        assert_eq!(image.get_dominant_color_opaque(128), [230, 120, 20]);
+        assert_eq!(image.get_dominant_color_opaque(0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn lab_clustering_separates_pastels() {
+        // Equal areas of two pastels and two dark grays. The grays are further apart in RGB
+        // but nearly the same to the eye, so only Lab clustering keeps the pastels apart.
+        let colors = [[255, 220, 220], [220, 255, 220], [10, 10, 10], [45, 45, 45]];
+        let mut image = handle(RgbImage::from_fn(40, 10, |x, _| {
+            Rgb(colors[x as usize / 10])
+        }));
+        let has = |palette: &[[u8; 3]], rgb: [u8; 3]| {
+            palette
+                .iter()
+                .any(|value| color::delta_e(*value, rgb) < 2.0)
+        };
+        let lab = image.get_dominant_colors_lab(3);
+        assert!(has(&lab, colors[0]) && has(&lab, colors[1]), "{:?}", lab);
+        let rgb = image.get_dominant_colors(3);
+        assert!(!has(&rgb, colors[0]) && !has(&rgb, colors[1]), "{:?}", rgb);
+    }
+
+    #[test]
+    fn perceptual_hash_survives_jpeg_round_trip() {
+        let original = RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+        });
+        let mut jpeg = std::io::Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(original.clone())
+            .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(80))
+            .unwrap();
+        let decoded = HandleImage::set_from_bytes(jpeg.get_ref()).unwrap();
+        let hash = handle(original).perceptual_hash();
+        assert!(hamming_distance(hash, decoded.perceptual_hash()) <= 4);
+        let flipped = handle(RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([(252 - x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+        }));
+        assert!(hamming_distance(hash, flipped.perceptual_hash()) > 16);
+    }
+
+    #[test]
+    fn bucketed_colors_collapse_noise() {
+        let mut image = handle(RgbImage::from_fn(30, 30, |x, y| {
+            let noise = ((x * 7 + y * 13) % 9) as u8;
+            Rgb(if x < 15 {
+                [120 + noise, 60 + noise, 30]
+            } else {
+                [30, 90 + noise, 200 - noise]
+            })
+        }));
+        let raw = image.get_colors().len();
+        let bucketed = image.get_colors_bucketed(32).unwrap();
+        assert!(
+            bucketed.len() < raw,
+            "{} buckets for {} colors",
+            bucketed.len(),
+            raw
+        );
+        assert!(bucketed.len() <= 8);
+        assert_eq!(image.get_colors_bucketed(1).unwrap().len(), raw);
+        for bucket_size in [0, 129] {
+            assert!(matches!(
+                image.get_colors_bucketed(bucket_size),
+                Err(ColorPaletteError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn extremes_of_gradient_skip_rare_colors() {
+        let mut image = handle(RgbImage::from_fn(64, 16, |x, y| {
+            Rgb(match (x, y) {
+                (0, 0) => [0, 0, 0],
+                (1, 0) => [255, 255, 255],
+                _ => [40 + x as u8 * 2; 3],
+            })
+        }));
+        let (darkest, lightest) = image.get_extremes(3);
+        assert_eq!(darkest, vec![[40; 3], [42; 3], [44; 3]]);
+        assert_eq!(lightest, vec![[166; 3], [164; 3], [162; 3]]);
+    }
+
+    #[test]
+    fn extremes_fall_back_when_every_color_is_rare() {
+        let mut image = handle(RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([x as u8 * 4, y as u8 * 5, 0])
+        }));
+        let (darkest, lightest) = image.get_extremes(4);
+        assert_eq!(darkest[0], [0, 0, 0]);
+        assert_eq!(lightest[0], [252, 235, 0]);
+        let luminance = |colors: &[[u8; 3]]| {
+            colors
+                .iter()
+                .map(|value| color::relative_luminance(*value))
+                .collect::<Vec<f32>>()
+        };
+        assert!(luminance(&darkest)
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1]));
+        assert!(luminance(&lightest)
+            .windows(2)
+            .all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn diverse_palette_reaches_every_corner_without_repeated_colors() {
+        let mut image = handle(RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([x as u8 * 4, y as u8 * 5, 77])
+        }));
+        assert_eq!(image.count_unique_colors(), 64 * 48);
+        let palette = image.get_diverse_palette(4);
+        assert_eq!(palette.len(), 4);
+        assert!(palette.iter().any(|value| value[0] >= 128), "{:?}", palette);
+        let quadrants: HashSet<(bool, bool)> = image
+            .get_diverse_palette(8)
+            .iter()
+            .map(|value| (value[0] >= 128, value[1] >= 120))
+            .collect();
+        assert_eq!(quadrants.len(), 4);
+    }
+}
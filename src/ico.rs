@@ -0,0 +1,136 @@
+use crate::{error::Result, ColorPaletteError};
+
+const HEADER_LEN: usize = 6;
+const ENTRY_LEN: usize = 16;
+
+struct Entry {
+    index: usize,
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+}
+
+fn entries(bytes: &[u8]) -> Result<Vec<Entry>> {
+    if bytes.len() < HEADER_LEN || bytes[0..4] != [0, 0, 1, 0] {
+        return Err(ColorPaletteError::decode("not an ICO file"));
+    }
+    let count = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+    if bytes.len() < HEADER_LEN + count * ENTRY_LEN {
+        return Err(ColorPaletteError::decode("ICO directory is truncated"));
+    }
+    // A stored size of 0 means 256.
+    let size = |value: u8| match value {
+        0 => 256,
+        value => value as u32,
+    };
+    Ok((0..count)
+        .map(|index| {
+            let entry = &bytes[HEADER_LEN + index * ENTRY_LEN..][..ENTRY_LEN];
+            Entry {
+                index,
+                width: size(entry[0]),
+                height: size(entry[1]),
+                bits_per_pixel: u16::from_le_bytes([entry[6], entry[7]]),
+            }
+        })
+        .collect())
+}
+
+/// Rewrites a multi-image ICO so it lists only the image at `index`, or the largest
+/// image if `index` is `None`. Ties in size go to the higher bit depth, then the first entry.
+/// `image` on its own prefers bit depth over size, which can pick a small icon.
+pub(crate) fn select(bytes: &[u8], index: Option<usize>) -> Result<Vec<u8>> {
+    let entries = entries(bytes)?;
+    let chosen = match index {
+        Some(index) if index < entries.len() => index,
+        Some(index) => {
+            return Err(ColorPaletteError::InvalidArgument(format!(
+                "ICO image index {} is out of range, the file has {} images",
+                index,
+                entries.len()
+            )))
+        }
+        None => entries
+            .iter()
+            .max_by_key(|entry| {
+                (
+                    entry.width * entry.height,
+                    entry.bits_per_pixel,
+                    std::cmp::Reverse(entry.index),
+                )
+            })
+            .map(|entry| entry.index)
+            .ok_or_else(|| ColorPaletteError::decode("ICO file contains no images"))?,
+    };
+    // Image offsets are absolute, so the layout is kept and only the directory is edited.
+    let mut out = bytes.to_vec();
+    out[4..6].copy_from_slice(&1u16.to_le_bytes());
+    out.copy_within(
+        HEADER_LEN + chosen * ENTRY_LEN..HEADER_LEN + (chosen + 1) * ENTRY_LEN,
+        HEADER_LEN,
+    );
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HandleImage;
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+    use std::io::Cursor;
+
+    /// A 16x16 blue icon listed with 32 bits per pixel followed by a 32x32 red one listed with
+    /// 24. `image` only reads RGBA PNGs from an ICO, so only the directory differs in depth.
+    fn two_sizes() -> Vec<u8> {
+        let png = |image: DynamicImage| {
+            let mut bytes = Cursor::new(vec![]);
+            image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+            bytes.into_inner()
+        };
+        let images = [
+            (
+                16,
+                32,
+                png(RgbaImage::from_pixel(16, 16, [0, 0, 255, 255].into()).into()),
+            ),
+            (
+                32,
+                24,
+                png(RgbaImage::from_pixel(32, 32, [255, 0, 0, 255].into()).into()),
+            ),
+        ];
+        let mut out = vec![0, 0, 1, 0, images.len() as u8, 0];
+        let mut offset = HEADER_LEN + images.len() * ENTRY_LEN;
+        for (size, bits_per_pixel, data) in &images {
+            out.extend([*size as u8, *size as u8, 0, 0]);
+            out.extend(1u16.to_le_bytes());
+            out.extend((*bits_per_pixel as u16).to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((offset as u32).to_le_bytes());
+            offset += data.len();
+        }
+        for (_, _, data) in images {
+            out.extend(data);
+        }
+        out
+    }
+
+    #[test]
+    fn largest_image_is_chosen() {
+        let mut image = HandleImage::set_from_bytes(&two_sizes()).unwrap();
+        assert_eq!(image.get_dimensions(), [32, 32]);
+        assert_eq!(image.get_dominant_color(), [255, 0, 0]);
+    }
+
+    #[test]
+    fn index_selects_image() {
+        let bytes = two_sizes();
+        let selected = select(&bytes, Some(0)).unwrap();
+        assert_eq!(selected[4..6], [1, 0]);
+        assert_eq!(selected[HEADER_LEN], 16);
+        assert!(matches!(
+            select(&bytes, Some(2)),
+            Err(ColorPaletteError::InvalidArgument(_))
+        ));
+    }
+}
@@ -1,2 +1,30 @@
+mod batch;
+mod builder;
+mod cache;
+mod cmyk;
+mod color;
+mod color_blindness;
+mod error;
+mod export;
 mod handle_image;
-pub use handle_image::HandleImage;
+mod ico;
+mod named_colors;
+#[cfg(feature = "exif")]
+mod orientation;
+mod palette;
+mod quantize;
+mod scheme;
+pub use batch::{palette_from_dir, palettes_from_urls};
+pub use builder::HandleImageBuilder;
+pub use color::{
+    are_colors_similar, best_text_color, contrast_ratio, delta_e, denormalize, from_hex, gradient,
+    hsl_to_rgb, hsv_to_rgb, lab_to_rgb, mix, normalize, pack_rgb, palette_contains_similar,
+    relative_luminance, rgb_to_hsl, rgb_to_hsv, rgb_to_lab, to_hex, unpack_rgb,
+};
+pub use color_blindness::{simulate_color_blindness, ColorBlindness};
+pub use error::ColorPaletteError;
+pub use handle_image::{dimensions_only, hamming_distance, palette_similarity, HandleImage};
+pub use image::imageops::FilterType;
+pub use named_colors::{nearest_css_name, CSS_COLORS};
+pub use palette::Palette;
+pub use scheme::{analogous, complementary, monochromatic, triadic};
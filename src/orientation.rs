@@ -0,0 +1,44 @@
+use image::DynamicImage;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Seek},
+    path::Path,
+};
+
+const NO_TRANSFORM: u32 = 1;
+
+pub(crate) fn from_reader<R: BufRead + Seek>(reader: &mut R) -> u32 {
+    exif::Reader::new()
+        .read_from_container(reader)
+        .ok()
+        .and_then(|data| {
+            data.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(NO_TRANSFORM)
+}
+
+pub(crate) fn from_path(path: &Path) -> u32 {
+    match File::open(path) {
+        Ok(file) => from_reader(&mut BufReader::new(file)),
+        Err(_) => NO_TRANSFORM,
+    }
+}
+
+pub(crate) fn from_bytes(bytes: &[u8]) -> u32 {
+    from_reader(&mut Cursor::new(bytes))
+}
+
+/// Rotates and flips `image` so that it is upright for the given EXIF orientation (1-8).
+pub(crate) fn apply(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
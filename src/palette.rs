@@ -0,0 +1,12 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A palette extracted from an image: the most frequent colors with their pixel counts,
+/// in descending order of frequency, plus the dominant color.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+    pub counts: Vec<u32>,
+    pub dominant: [u8; 3],
+}
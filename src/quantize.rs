@@ -0,0 +1,284 @@
+const KMEANS_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+const KMEANS_MAX_ITERATIONS: usize = 20;
+const KMEANS_MIN_SHIFT: f32 = 1.0;
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn distance_squared(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn nearest(point: &[f32; 3], centroids: &[[f32; 3]]) -> usize {
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+    for (i, centroid) in centroids.iter().enumerate() {
+        let distance = distance_squared(point, centroid);
+        if distance < best_distance {
+            best = i;
+            best_distance = distance;
+        }
+    }
+    best
+}
+
+fn seed_centroids(points: &[([f32; 3], u32)], k: usize) -> Vec<[f32; 3]> {
+    let mut rng = XorShift(KMEANS_SEED);
+    let mut centroids = vec![];
+    let heaviest = points
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, (_, count))| (*count, std::cmp::Reverse(*i)))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    centroids.push(points[heaviest].0);
+    let mut distances: Vec<f64> = points
+        .iter()
+        .map(|(point, count)| distance_squared(point, &centroids[0]) as f64 * *count as f64)
+        .collect();
+    while centroids.len() < k {
+        let total: f64 = distances.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut target = rng.next_f64() * total;
+        let mut chosen = points.len() - 1;
+        for (i, distance) in distances.iter().enumerate() {
+            if target < *distance {
+                chosen = i;
+                break;
+            }
+            target -= distance;
+        }
+        let centroid = points[chosen].0;
+        centroids.push(centroid);
+        for (distance, (point, count)) in distances.iter_mut().zip(points) {
+            let candidate = distance_squared(point, &centroid) as f64 * *count as f64;
+            if candidate < *distance {
+                *distance = candidate;
+            }
+        }
+    }
+    centroids
+}
+
+/// Weighted k-means over `points`, seeded with k-means++ from a fixed seed.
+/// Returns the centroids with their cluster weight, most populous first.
+/// `points` must be in a deterministic order for the result to be reproducible.
+pub(crate) fn kmeans(points: &[([f32; 3], u32)], k: usize) -> Vec<([f32; 3], u32)> {
+    let k = k.min(points.len());
+    if k == 0 {
+        return vec![];
+    }
+    let mut centroids = seed_centroids(points, k);
+    let mut sizes = vec![0u32; centroids.len()];
+    for _ in 0..KMEANS_MAX_ITERATIONS {
+        let mut sums = vec![[0.0f64; 3]; centroids.len()];
+        sizes = vec![0u32; centroids.len()];
+        for (point, count) in points {
+            let cluster = nearest(point, &centroids);
+            for channel in 0..3 {
+                sums[cluster][channel] += point[channel] as f64 * *count as f64;
+            }
+            sizes[cluster] += count;
+        }
+        let mut max_shift = 0.0f32;
+        for (i, centroid) in centroids.iter_mut().enumerate() {
+            if sizes[i] == 0 {
+                continue;
+            }
+            let updated = [
+                (sums[i][0] / sizes[i] as f64) as f32,
+                (sums[i][1] / sizes[i] as f64) as f32,
+                (sums[i][2] / sizes[i] as f64) as f32,
+            ];
+            max_shift = max_shift.max(distance_squared(centroid, &updated).sqrt());
+            *centroid = updated;
+        }
+        if max_shift < KMEANS_MIN_SHIFT {
+            break;
+        }
+    }
+    let mut clusters: Vec<([f32; 3], u32)> = centroids
+        .into_iter()
+        .zip(sizes)
+        .filter(|(_, size)| *size > 0)
+        .collect();
+    clusters.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    clusters
+}
+
+fn channel_range(bucket: &[([u8; 3], u32)], channel: usize) -> u8 {
+    let min = bucket.iter().map(|(c, _)| c[channel]).min().unwrap_or(0);
+    let max = bucket.iter().map(|(c, _)| c[channel]).max().unwrap_or(0);
+    max - min
+}
+
+fn widest_channel(bucket: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| (channel, channel_range(bucket, channel)))
+        .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+        .unwrap()
+}
+
+pub(crate) fn weighted_average(bucket: &[([u8; 3], u32)]) -> ([u8; 3], u32) {
+    let mut sums = [0u64; 3];
+    let mut total = 0u64;
+    for (color, count) in bucket {
+        for channel in 0..3 {
+            sums[channel] += color[channel] as u64 * *count as u64;
+        }
+        total += *count as u64;
+    }
+    let average = |sum: u64| (sum as f64 / total.max(1) as f64).round() as u8;
+    (
+        [average(sums[0]), average(sums[1]), average(sums[2])],
+        total as u32,
+    )
+}
+
+/// Median-cut quantization over a color frequency table.
+/// Returns the weighted average of each bucket with its pixel count, most populous first.
+pub(crate) fn median_cut(colors: &[([u8; 3], u32)], n: usize) -> Vec<([u8; 3], u32)> {
+    if n == 0 || colors.is_empty() {
+        return vec![];
+    }
+    let mut buckets = vec![colors.to_vec()];
+    while buckets.len() < n {
+        let candidate = buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.len() > 1)
+            .map(|(i, bucket)| (i, widest_channel(bucket)))
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|(i, (_, range))| (*range, std::cmp::Reverse(*i)));
+        let (index, (channel, _)) = match candidate {
+            Some(candidate) => candidate,
+            None => break,
+        };
+        let mut bucket = buckets.swap_remove(index);
+        bucket.sort_by_key(|(color, _)| (color[channel], *color));
+        let half: u64 = bucket.iter().map(|(_, count)| *count as u64).sum::<u64>() / 2;
+        let mut seen = 0u64;
+        let mut split = 1;
+        for (i, (_, count)) in bucket.iter().enumerate() {
+            seen += *count as u64;
+            if seen >= half {
+                split = i + 1;
+                break;
+            }
+        }
+        let split = split.clamp(1, bucket.len() - 1);
+        let upper = bucket.split_off(split);
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+    let mut palette: Vec<([u8; 3], u32)> = buckets.iter().map(|b| weighted_average(b)).collect();
+    palette.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    palette
+}
+
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    count: u64,
+    sums: [u64; 3],
+}
+
+fn octree_index(color: &[u8; 3], level: usize) -> usize {
+    let shift = 7 - level;
+    (((color[0] >> shift) & 1) << 2 | ((color[1] >> shift) & 1) << 1 | ((color[2] >> shift) & 1))
+        as usize
+}
+
+/// Octree quantization over a color frequency table. Colors are inserted by their RGB
+/// bit-planes, then the least populated nodes of the deepest level are folded into their
+/// parent until at most `max_colors` leaves remain.
+/// Returns each leaf's weighted average with its pixel count, most populous first.
+pub(crate) fn octree(colors: &[([u8; 3], u32)], max_colors: usize) -> Vec<([u8; 3], u32)> {
+    if max_colors == 0 || colors.is_empty() {
+        return vec![];
+    }
+    let empty = || OctreeNode {
+        children: [None; 8],
+        count: 0,
+        sums: [0; 3],
+    };
+    let mut nodes = vec![empty()];
+    let mut levels: Vec<Vec<usize>> = vec![vec![]; 8];
+    levels[0].push(0);
+    let mut leaves = 0;
+    for (color, count) in colors {
+        let mut node = 0;
+        for level in 0..8 {
+            nodes[node].count += *count as u64;
+            let index = octree_index(color, level);
+            node = match nodes[node].children[index] {
+                Some(child) => child,
+                None => {
+                    nodes.push(empty());
+                    let child = nodes.len() - 1;
+                    nodes[node].children[index] = Some(child);
+                    if level == 7 {
+                        leaves += 1;
+                    } else {
+                        levels[level + 1].push(child);
+                    }
+                    child
+                }
+            };
+        }
+        nodes[node].count += *count as u64;
+        for (sum, value) in nodes[node].sums.iter_mut().zip(color) {
+            *sum += *value as u64 * *count as u64;
+        }
+    }
+    for level in (0..8).rev() {
+        if leaves <= max_colors {
+            break;
+        }
+        let mut reducible = std::mem::take(&mut levels[level]);
+        reducible.sort_by_key(|node| nodes[*node].count);
+        for node in reducible {
+            if leaves <= max_colors {
+                break;
+            }
+            let children: Vec<usize> = nodes[node].children.iter().flatten().copied().collect();
+            for child in &children {
+                for channel in 0..3 {
+                    nodes[node].sums[channel] += nodes[*child].sums[channel];
+                }
+            }
+            nodes[node].children = [None; 8];
+            leaves = leaves + 1 - children.len();
+        }
+    }
+    let mut palette = vec![];
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let children: Vec<usize> = nodes[node].children.iter().flatten().copied().collect();
+        if children.is_empty() {
+            let node = &nodes[node];
+            let average = |sum: u64| (sum as f64 / node.count.max(1) as f64).round() as u8;
+            palette.push((
+                [
+                    average(node.sums[0]),
+                    average(node.sums[1]),
+                    average(node.sums[2]),
+                ],
+                node.count as u32,
+            ));
+        } else {
+            stack.extend(children);
+        }
+    }
+    palette.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    palette
+}
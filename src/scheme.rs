@@ -0,0 +1,55 @@
+use crate::color::{hsl_to_rgb, rgb_to_hsl};
+
+fn rotate_hue(rgb: [u8; 3], degrees: f32) -> [u8; 3] {
+    let (h, s, l) = rgb_to_hsl(rgb);
+    hsl_to_rgb(h + degrees, s, l)
+}
+
+/// The color on the opposite side of the color wheel.
+pub fn complementary(rgb: [u8; 3]) -> [u8; 3] {
+    rotate_hue(rgb, 180.0)
+}
+
+/// The two neighbours `spread` degrees on either side of the color's hue.
+pub fn analogous(rgb: [u8; 3], spread: f32) -> [[u8; 3]; 2] {
+    [rotate_hue(rgb, -spread), rotate_hue(rgb, spread)]
+}
+
+/// The two colors that form an equilateral triangle with the color on the color wheel.
+pub fn triadic(rgb: [u8; 3]) -> [[u8; 3]; 2] {
+    [rotate_hue(rgb, 120.0), rotate_hue(rgb, 240.0)]
+}
+
+/// `steps` tints and shades of the color, from dark to light, keeping its hue and saturation.
+/// Lightness is spaced evenly strictly between black and white, so no step clips to either;
+/// a single step returns the color itself.
+pub fn monochromatic(rgb: [u8; 3], steps: usize) -> Vec<[u8; 3]> {
+    if steps == 1 {
+        return vec![rgb];
+    }
+    let (h, s, _) = rgb_to_hsl(rgb);
+    (1..=steps)
+        .map(|step| hsl_to_rgb(h, s, step as f32 / (steps + 1) as f32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monochromatic_keeps_hue() {
+        let base = [200, 80, 40];
+        let (hue, _, _) = rgb_to_hsl(base);
+        let steps = monochromatic(base, 5);
+        assert_eq!(steps.len(), 5);
+        let mut last_lightness = 0.0;
+        for rgb in steps {
+            let (h, _, l) = rgb_to_hsl(rgb);
+            assert!((h - hue).abs() < 2.0, "{:?} has hue {}", rgb, h);
+            assert!(l > last_lightness);
+            last_lightness = l;
+        }
+        assert_eq!(monochromatic(base, 1), vec![base]);
+    }
+}